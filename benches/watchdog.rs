@@ -0,0 +1,79 @@
+#[allow(dead_code)]
+#[path = "../src/config.rs"]
+mod config;
+// An inline module, so emulator.rs finds its `alacritty` submodule where the crate does.
+#[path = "../src"]
+mod src {
+    #[allow(dead_code)]
+    pub mod emulator;
+}
+#[allow(dead_code)]
+#[path = "../src/state.rs"]
+mod state;
+#[allow(dead_code)]
+#[path = "../src/watchdog.rs"]
+mod watchdog;
+
+use clap::ValueEnum;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use src::emulator::{self, EmulatorKind, TerminalEmulator};
+use watchdog::{Reach, Watchdog};
+
+const PANES: usize = 9;
+const ROWS: u16 = 40;
+const COLS: u16 = 120;
+
+fn build_output_screen(kind: EmulatorKind, seed: usize) -> Box<dyn TerminalEmulator> {
+    let mut emulator = emulator::new_emulator(kind, ROWS, COLS, 1000);
+    for i in 0..2000 {
+        let line = format!(
+            "\x1b[32m   Compiling\x1b[0m crate-{}-{} v0.{}.{} (/home/dev/work/crate-{})\r\n",
+            seed,
+            i,
+            i % 7,
+            i % 13,
+            i
+        );
+        emulator.process(line.as_bytes());
+    }
+    emulator
+}
+
+fn bench_watchdog(c: &mut Criterion) {
+    let watchdog = Watchdog::new();
+    let whole_screen = Reach {
+        whole_screen: true,
+        ..Reach::default()
+    };
+
+    for &kind in EmulatorKind::value_variants() {
+        let name = kind.to_possible_value().unwrap();
+        let name = name.get_name();
+        let emulators: Vec<_> = (0..PANES)
+            .map(|seed| build_output_screen(kind, seed))
+            .collect();
+
+        c.bench_function(&format!("{name}: 9 panes, full screen contents"), |b| {
+            b.iter(|| {
+                for emulator in &emulators {
+                    let context =
+                        watchdog::watch_context(emulator.as_ref(), ROWS, COLS, whole_screen);
+                    black_box(watchdog.classify(&context));
+                }
+            })
+        });
+
+        c.bench_function(&format!("{name}: 9 panes, cursor rows only"), |b| {
+            b.iter(|| {
+                for emulator in &emulators {
+                    let context =
+                        watchdog::watch_context(emulator.as_ref(), ROWS, COLS, watchdog.reach());
+                    black_box(watchdog.classify(&context));
+                }
+            })
+        });
+    }
+}
+
+criterion_group!(benches, bench_watchdog);
+criterion_main!(benches);
@@ -0,0 +1,41 @@
+# termoil shell integration for bash: OSC 133 marks around prompts and commands, so termoil
+# knows when the pane is at a prompt, when a command runs, and how it exited.
+#
+#   eval "$(termoil shell-init bash)"    # in ~/.bashrc
+#
+# Commands are detected with a DEBUG trap, which replaces any DEBUG trap already set.
+
+if [[ -n "$TERMOIL_PANE_ID" && -z "$__termoil_integrated" ]]; then
+    __termoil_integrated=1
+    __termoil_at_prompt=
+    __termoil_running=
+
+    __termoil_prompt_start() {
+        local status=$?
+        __termoil_at_prompt=
+        if [[ -n "$__termoil_running" ]]; then
+            printf '\e]133;D;%s\a' "$status"
+            __termoil_running=
+        fi
+        printf '\e]133;A\a'
+        return "$status"
+    }
+
+    __termoil_prompt_end() {
+        __termoil_at_prompt=1
+    }
+
+    __termoil_preexec() {
+        [[ -n "$__termoil_at_prompt" && -z "$COMP_LINE" ]] || return 0
+        # An empty command line runs PROMPT_COMMAND straight away, which isn't a command.
+        [[ "$BASH_COMMAND" == __termoil_* ]] && return 0
+        __termoil_at_prompt=
+        __termoil_running=1
+        printf '\e]133;C\a'
+    }
+
+    # Newlines rather than `;`, which would break on a PROMPT_COMMAND already ending in one.
+    PROMPT_COMMAND=$'__termoil_prompt_start\n'"$PROMPT_COMMAND"$'\n__termoil_prompt_end'
+    PS1="$PS1"'\[\e]133;B\a\]'
+    trap '__termoil_preexec' DEBUG
+fi
@@ -0,0 +1,20 @@
+# termoil shell integration for fish: OSC 133 marks around prompts and commands, so termoil
+# knows when the pane is at a prompt, when a command runs, and how it exited.
+#
+#   termoil shell-init fish | source    # in ~/.config/fish/config.fish
+
+if set -q TERMOIL_PANE_ID; and not set -q __termoil_integrated
+    set -g __termoil_integrated 1
+
+    function __termoil_prompt --on-event fish_prompt
+        printf '\e]133;A\a'
+    end
+
+    function __termoil_preexec --on-event fish_preexec
+        printf '\e]133;C\a'
+    end
+
+    function __termoil_postexec --on-event fish_postexec
+        printf '\e]133;D;%s\a' $status
+    end
+end
@@ -0,0 +1,247 @@
+//! `termoil watchdog test`: replays a captured screen through a terminal emulator and reports
+//! what the watchdog makes of it.
+
+use crate::emulator::{self, EmulatorKind, TerminalEmulator};
+use crate::state::PaneState;
+use crate::watchdog::{self, Match, Watchdog};
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use serde_json::Value;
+use std::io::Write;
+use std::path::Path;
+
+/// Output to replay, as timed chunks.
+pub struct Capture {
+    pub rows: u16,
+    pub cols: u16,
+    /// Seconds into the recording, for asciicasts; `None` for a plain capture.
+    pub frames: Vec<(Option<f64>, Vec<u8>)>,
+}
+
+impl Capture {
+    /// Reads an asciicast (v2) or a plain text/ANSI capture such as `tmux capture-pane -e`
+    /// output. `rows`/`cols` override the recorded or measured size.
+    pub fn load(path: &Path, rows: Option<u16>, cols: Option<u16>) -> Result<Self> {
+        let data = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+        let text = String::from_utf8_lossy(&data);
+        let header = text
+            .lines()
+            .next()
+            .and_then(|line| serde_json::from_str::<Value>(line).ok())
+            .filter(|header| header.get("version").is_some());
+        let mut capture = match header {
+            Some(header) => Self::from_asciicast(&header, &text)
+                .with_context(|| format!("parsing {}", path.display()))?,
+            None => Self::from_text(&text),
+        };
+        capture.rows = rows.unwrap_or(capture.rows).max(1);
+        capture.cols = cols.unwrap_or(capture.cols).max(1);
+        Ok(capture)
+    }
+
+    fn from_asciicast(header: &Value, text: &str) -> Result<Self> {
+        if header["version"].as_u64() != Some(2) {
+            bail!("only asciicast v2 recordings are supported");
+        }
+        let size = |key: &str| header[key].as_u64().map(|n| n.min(u16::MAX as u64) as u16);
+        let mut frames = Vec::new();
+        for (idx, line) in text.lines().enumerate().skip(1) {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let event: Value = serde_json::from_str(line)
+                .with_context(|| format!("line {}: not a JSON event", idx + 1))?;
+            if event[1].as_str() == Some("o") {
+                let data = event[2].as_str().unwrap_or("");
+                frames.push((event[0].as_f64(), data.as_bytes().to_vec()));
+            }
+        }
+        Ok(Self {
+            rows: size("height").unwrap_or(24),
+            cols: size("width").unwrap_or(80),
+            frames,
+        })
+    }
+
+    /// A capture is sized to its own text, and ends with the cursor after its last
+    /// non-blank character, where a live prompt would leave it.
+    fn from_text(text: &str) -> Self {
+        let escapes =
+            Regex::new(r"\x1b(\[[0-9;?:<=>]*[ -/]*[@-~]|\][^\x07\x1b]*(\x07|\x1b\\)|[ -/]*[0-~])")
+                .expect("escape pattern");
+        let text = text.trim_end();
+        let lines: Vec<&str> = text.lines().collect();
+        let cols = lines
+            .iter()
+            .map(|line| escapes.replace_all(line, "").chars().count())
+            .max()
+            .unwrap_or(0);
+        Self {
+            rows: lines.len().clamp(1, u16::MAX as usize) as u16,
+            cols: cols.clamp(80, u16::MAX as usize) as u16,
+            frames: vec![(None, lines.join("\r\n").into_bytes())],
+        }
+    }
+}
+
+fn report(
+    out: &mut impl Write,
+    emulator: &dyn TerminalEmulator,
+    state: PaneState,
+    matches: &[Match],
+) -> Result<()> {
+    if matches.is_empty() {
+        if state == PaneState::Idle {
+            writeln!(
+                out,
+                "  state:   idle (the cursor is at a shell prompt, so no rules apply)"
+            )?;
+        } else {
+            writeln!(out, "  state:   {} (no rule matched)", state.label())?;
+        }
+        return Ok(());
+    }
+    writeln!(out, "  state:   {}", state.label())?;
+    // The first match decides the state; the rest would take over if it were disabled.
+    for (idx, found) in matches.iter().enumerate() {
+        let label = if idx == 0 { "rule:" } else { "also:" };
+        writeln!(out, "  {:<8} {} ({})", label, found.rule, found.pattern)?;
+        writeln!(
+            out,
+            "  match:   {:?} at line {}, column {}",
+            found.text,
+            found.row + 1,
+            found.col + 1
+        )?;
+        let line = emulator.rows_text(found.row, found.row);
+        writeln!(out, "  {:>5} | {}", found.row + 1, line.trim_end())?;
+    }
+    Ok(())
+}
+
+/// Replays `capture` and prints the watchdog's verdict, with every rule that matches: for an
+/// asciicast, each time it changes, then on the final screen. Returns the rule deciding the
+/// final screen's state.
+pub fn test(
+    watchdog: &Watchdog,
+    kind: EmulatorKind,
+    capture: &Capture,
+    out: &mut impl Write,
+) -> Result<Option<Match>> {
+    let mut emulator = emulator::new_emulator(kind, capture.rows, capture.cols, 0);
+    let verdict = |emulator: &dyn TerminalEmulator| {
+        let context =
+            watchdog::watch_context(emulator, capture.rows, capture.cols, watchdog.reach());
+        (watchdog.classify(&context).0, watchdog.matches(&context))
+    };
+    let timed = capture.frames.iter().any(|(time, _)| time.is_some());
+    let mut last: Option<(PaneState, Vec<String>)> = None;
+    for (time, data) in &capture.frames {
+        emulator.process(data);
+        if let Some(time) = time {
+            let (state, matches) = verdict(emulator.as_ref());
+            let rules = matches.iter().map(|found| found.rule.clone()).collect();
+            let changed = (state, rules);
+            if last.as_ref() != Some(&changed) {
+                writeln!(out, "at {time:.2}s:")?;
+                report(out, emulator.as_ref(), state, &matches)?;
+                last = Some(changed);
+            }
+        }
+    }
+    let (state, matches) = verdict(emulator.as_ref());
+    if timed {
+        writeln!(out, "final screen:")?;
+    } else {
+        writeln!(out, "screen ({}x{}):", capture.cols, capture.rows)?;
+    }
+    report(out, emulator.as_ref(), state, &matches)?;
+    Ok(matches.into_iter().next())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn asciicast(lines: &[&str]) -> Result<Capture> {
+        let text = lines.join("\n");
+        let header = serde_json::from_str(lines[0]).unwrap();
+        Capture::from_asciicast(&header, &text)
+    }
+
+    #[test]
+    fn text_captures_are_sized_to_their_text() {
+        let capture = Capture::from_text("one\ntwo\nProceed? [y/n]  \n\n");
+        assert_eq!((capture.rows, capture.cols), (3, 80));
+        assert_eq!(
+            capture.frames,
+            vec![(None, b"one\r\ntwo\r\nProceed? [y/n]".to_vec())]
+        );
+
+        let wide = "x".repeat(100);
+        let capture = Capture::from_text(&format!("short\n{wide}"));
+        assert_eq!((capture.rows, capture.cols), (2, 100));
+
+        assert_eq!(Capture::from_text("").rows, 1);
+    }
+
+    #[test]
+    fn text_captures_measure_without_escapes() {
+        let colored = format!(
+            "\x1b[1;38;5;196m{}\x1b[0m\x1b]8;;https://example.com\x07link\x1b]8;;\x1b\\\x1b(B",
+            "y".repeat(90)
+        );
+        let capture = Capture::from_text(&colored);
+        assert_eq!(capture.cols, 94);
+        // The escapes themselves are still replayed.
+        assert_eq!(capture.frames[0].1, colored.as_bytes());
+    }
+
+    #[test]
+    fn asciicasts_replay_output_events() {
+        let header = json!({"version": 2, "width": 100, "height": 30}).to_string();
+        let capture = asciicast(&[
+            &header,
+            r#"[0.5, "o", "hello\r\n"]"#,
+            r#"[0.7, "i", "y"]"#,
+            "",
+            r#"[1.25, "o", "Proceed?"]"#,
+        ])
+        .unwrap();
+        assert_eq!((capture.rows, capture.cols), (30, 100));
+        assert_eq!(
+            capture.frames,
+            vec![
+                (Some(0.5), b"hello\r\n".to_vec()),
+                (Some(1.25), b"Proceed?".to_vec())
+            ]
+        );
+
+        let unsized_cast = asciicast(&[r#"{"version": 2}"#]).unwrap();
+        assert_eq!((unsized_cast.rows, unsized_cast.cols), (24, 80));
+    }
+
+    #[test]
+    fn other_asciicast_versions_are_rejected() {
+        let err = asciicast(&[r#"{"version": 1, "width": 80, "height": 24}"#])
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("v2"));
+        let err = asciicast(&[r#"{"version": 2}"#, "not json"]).err().unwrap();
+        assert!(err.to_string().contains("line 2"));
+    }
+
+    #[test]
+    fn reports_every_matching_rule() {
+        let capture = Capture::from_text("Continue?\nbuilding\nProceed? [y/n]");
+        let mut out = Vec::new();
+        let found = test(&Watchdog::new(), EmulatorKind::Vt100, &capture, &mut out).unwrap();
+        assert_eq!(found.map(|found| found.rule), Some("y/n".to_string()));
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("rule:    y/n"), "{out}");
+        assert!(out.contains("also:    proceed"), "{out}");
+        assert!(out.contains("also:    continue"), "{out}");
+        assert!(out.contains("      1 | Continue?"), "{out}");
+    }
+}
@@ -0,0 +1,175 @@
+//! User configuration, read from `$XDG_CONFIG_HOME/termoil/config.json` (falling back to
+//! `~/.config/termoil/config.json`) unless `--config` names another file.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Seconds a pane may go without output, away from a shell prompt, before it's flagged
+    /// as stalled; 0 never flags it.
+    pub stall_after_secs: u64,
+    /// Minutes in the attention queue before a pane's alert is escalated; 0 never escalates.
+    pub escalate_after_mins: u64,
+    /// Milliseconds a waiting screen must sit unchanged before the pane is flagged, so
+    /// prompts that scroll past or flash by in a redraw are ignored.
+    pub attention_stable_ms: u64,
+    /// Milliseconds a flagged pane must stop looking like it's waiting before the flag clears.
+    pub attention_clear_ms: u64,
+    /// Seconds a command must run for before its completion marks the pane finished and
+    /// notifies; 0 never does.
+    pub finished_after_secs: u64,
+    pub notify: NotifyConfig,
+    /// Watchdog rules added to the built-in ones; a rule named like a built-in replaces it.
+    pub rules: Vec<RuleConfig>,
+    /// Names of built-in rules to drop.
+    pub disable_rules: Vec<String>,
+    pub profiles: Vec<Profile>,
+}
+
+/// How termoil reaches the user when a pane starts waiting, and again on escalation.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotifyConfig {
+    /// Ring the bell of the terminal termoil runs in.
+    pub bell: bool,
+    /// Shell command to run, with `TERMOIL_PANE`, `TERMOIL_STATE` and `TERMOIL_MESSAGE`
+    /// set, e.g. `notify-send termoil "$TERMOIL_MESSAGE"`.
+    pub command: Option<String>,
+}
+
+/// Settings for panes whose foreground process has a given name.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub name: String,
+    /// Foreground process name the profile applies to, e.g. `claude` or `aider`.
+    pub command: String,
+    /// Overrides the global `stall_after_secs`; 0 turns stall detection off for these panes.
+    #[serde(default)]
+    pub stall_after_secs: Option<u64>,
+    /// Rules layered over the global ones for these panes.
+    #[serde(default)]
+    pub rules: Vec<RuleConfig>,
+    #[serde(default)]
+    pub disable_rules: Vec<String>,
+}
+
+/// A watchdog rule: a pattern that marks a pane as waiting.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleConfig {
+    pub name: String,
+    pub pattern: String,
+    pub state: RuleState,
+    #[serde(default)]
+    pub scope: RuleScope,
+    /// Only match while the pane is (`true`) or isn't (`false`) on the alternate screen.
+    #[serde(default)]
+    pub alternate_screen: Option<bool>,
+    /// Patterns that suppress the rule when they match anywhere on the screen.
+    #[serde(default)]
+    pub unless: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleState {
+    Approval,
+    Input,
+}
+
+/// Which part of the screen a rule reads.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleScope {
+    /// The cursor row.
+    Cursor,
+    /// The cursor row and the rows just above it.
+    #[default]
+    Nearby,
+    /// The bottom N rows of the screen.
+    Bottom(u16),
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            stall_after_secs: 300,
+            escalate_after_mins: 10,
+            attention_stable_ms: 500,
+            attention_clear_ms: 1000,
+            finished_after_secs: 30,
+            notify: NotifyConfig::default(),
+            rules: Vec::new(),
+            disable_rules: Vec::new(),
+            profiles: Vec::new(),
+        }
+    }
+}
+
+fn default_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("termoil").join("config.json"))
+}
+
+impl Config {
+    /// Reads `path`, or the default location when `None`. Only a missing default file is
+    /// treated as an empty config.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let (path, explicit) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Self::default()),
+            },
+        };
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if !explicit && err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Self::default())
+            }
+            Err(err) => {
+                return Err(err).with_context(|| format!("reading {}", path.display()));
+            }
+        };
+        serde_json::from_str(&text).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    /// Index of the profile for a foreground process name.
+    pub fn profile_for(&self, command: &str) -> Option<usize> {
+        self.profiles
+            .iter()
+            .position(|profile| profile.command == command)
+    }
+
+    pub fn escalate_after(&self) -> Option<Duration> {
+        (self.escalate_after_mins > 0).then(|| Duration::from_secs(self.escalate_after_mins * 60))
+    }
+
+    pub fn finished_after(&self) -> Option<Duration> {
+        (self.finished_after_secs > 0).then(|| Duration::from_secs(self.finished_after_secs))
+    }
+
+    pub fn attention_stable(&self) -> Duration {
+        Duration::from_millis(self.attention_stable_ms)
+    }
+
+    pub fn attention_clear(&self) -> Duration {
+        Duration::from_millis(self.attention_clear_ms)
+    }
+
+    pub fn stall_after(&self, profile: Option<usize>) -> Option<Duration> {
+        let secs = profile
+            .and_then(|idx| self.profiles.get(idx))
+            .and_then(|profile| profile.stall_after_secs)
+            .unwrap_or(self.stall_after_secs);
+        (secs > 0).then(|| Duration::from_secs(secs))
+    }
+}
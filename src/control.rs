@@ -0,0 +1,247 @@
+//! Local control socket, so programs running in panes (agent hooks, scripts) can report
+//! their state directly instead of relying on the watchdog reading their screen.
+//!
+//! Each pane's child gets `TERMOIL_SOCKET` and `TERMOIL_PANE_ID`; `termoil notify` uses them
+//! to send one JSON request per line and reads back `ok` or `error: ...`. A request with a
+//! `query` field (`{"query": "state", "pane": 3}`, or without `pane` for every pane) reads
+//! back a JSON array of pane states instead.
+
+use crate::state::PaneState;
+use crate::AppEvent;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::DirBuilder;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::Duration;
+
+pub const SOCKET_ENV: &str = "TERMOIL_SOCKET";
+pub const PANE_ENV: &str = "TERMOIL_PANE_ID";
+
+/// A state a program reports for its pane.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportedState {
+    /// Needs the user's approval.
+    Waiting,
+    /// Needs the user to type something.
+    Input,
+    Working,
+    Idle,
+    /// Withdraw the last report and go back to what the watchdog sees.
+    Clear,
+}
+
+impl ReportedState {
+    pub fn pane_state(self) -> Option<PaneState> {
+        match self {
+            ReportedState::Waiting => Some(PaneState::WaitingForApproval),
+            ReportedState::Input => Some(PaneState::WaitingForInput),
+            ReportedState::Working => Some(PaneState::Working),
+            ReportedState::Idle => Some(PaneState::Idle),
+            ReportedState::Clear => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Request {
+    pub pane: u32,
+    pub state: ReportedState,
+    #[serde(default)]
+    pub message: Option<String>,
+    /// The tool call the program is running or asking to run, e.g. `Bash: cargo test`.
+    #[serde(default)]
+    pub tool: Option<String>,
+}
+
+/// Asks the main loop for the state of one pane, or of every pane.
+pub struct StateQuery {
+    pub pane: Option<u32>,
+    pub reply: Sender<Vec<PaneStatus>>,
+}
+
+/// A pane's state, as answered to a `state` query.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PaneStatus {
+    pub pane: u32,
+    /// Position in the grid, as numbered in pane titles.
+    pub number: usize,
+    pub state: String,
+    pub attention: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Query {
+    query: QueryKind,
+    #[serde(default)]
+    pane: Option<u32>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum QueryKind {
+    State,
+}
+
+/// A pane's last report, held until the user types into the pane or a new report replaces
+/// it.
+pub struct Report {
+    pub state: PaneState,
+    pub message: Option<String>,
+    pub tool: Option<String>,
+}
+
+/// The listening socket; its file is removed on drop.
+pub struct Server {
+    pub path: PathBuf,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+fn socket_path() -> io::Result<PathBuf> {
+    let name = format!("termoil-{}.sock", std::process::id());
+    if let Some(dir) = std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        return Ok(PathBuf::from(dir).join(name));
+    }
+    // The temp dir is shared, so the socket goes in a directory only this user can enter.
+    // Its own permissions can only be narrowed once it's bound, which would leave a window
+    // for other users to connect.
+    let uid = unsafe { libc::getuid() };
+    let dir = std::env::temp_dir().join(format!("termoil-{uid}"));
+    match DirBuilder::new().mode(0o700).create(&dir) {
+        Err(err) if err.kind() != io::ErrorKind::AlreadyExists => return Err(err),
+        _ => {}
+    }
+    // It may have been there already, made by someone else.
+    let meta = std::fs::symlink_metadata(&dir)?;
+    if !meta.is_dir() || meta.uid() != uid || meta.mode() & 0o077 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} isn't a directory only you can use", dir.display()),
+        ));
+    }
+    Ok(dir.join(name))
+}
+
+/// Binds the control socket and forwards requests to the main loop as `AppEvent::Control`.
+pub fn listen(events: Sender<AppEvent>) -> io::Result<Server> {
+    let path = socket_path()?;
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let _ = handle_client(stream, &events);
+        }
+    });
+    Ok(Server { path })
+}
+
+fn handle_client(stream: UnixStream, events: &Sender<AppEvent>) -> io::Result<()> {
+    // A client that never finishes its line mustn't wedge the socket for everyone else.
+    stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+    let mut reader = BufReader::new(&stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let reply = match serde_json::from_str::<Value>(&line) {
+        Ok(value) if value.get("query").is_some() => answer_query(value, events),
+        Ok(value) => match serde_json::from_value::<Request>(value) {
+            Ok(request) => match events.send(AppEvent::Control(request)) {
+                Ok(()) => "ok".to_string(),
+                Err(_) => "error: termoil is shutting down".to_string(),
+            },
+            Err(err) => format!("error: {err}"),
+        },
+        Err(err) => format!("error: {err}"),
+    };
+    (&stream).write_all(format!("{reply}\n").as_bytes())
+}
+
+fn answer_query(value: Value, events: &Sender<AppEvent>) -> String {
+    let query = match serde_json::from_value::<Query>(value) {
+        Ok(query) => query,
+        Err(err) => return format!("error: {err}"),
+    };
+    let QueryKind::State = query.query;
+    let (reply, answer) = mpsc::channel();
+    let request = StateQuery {
+        pane: query.pane,
+        reply,
+    };
+    if events.send(AppEvent::Query(request)).is_err() {
+        return "error: termoil is shutting down".to_string();
+    }
+    match answer.recv_timeout(Duration::from_secs(1)) {
+        Ok(statuses) if statuses.is_empty() && query.pane.is_some() => {
+            format!("error: no pane {}", query.pane.unwrap_or_default())
+        }
+        Ok(statuses) => {
+            serde_json::to_string(&statuses).unwrap_or_else(|err| format!("error: {err}"))
+        }
+        Err(_) => "error: termoil didn't answer".to_string(),
+    }
+}
+
+/// The control socket and this process's pane id, from the environment.
+fn pane_env() -> Result<(std::ffi::OsString, u32)> {
+    let (Some(socket), Some(pane)) = (std::env::var_os(SOCKET_ENV), std::env::var(PANE_ENV).ok())
+    else {
+        bail!("not running inside a termoil pane ({SOCKET_ENV} and {PANE_ENV} are unset)");
+    };
+    let pane = pane
+        .parse()
+        .with_context(|| format!("{PANE_ENV} is not a pane id: {pane}"))?;
+    Ok((socket, pane))
+}
+
+/// Sends one request line and returns the reply, or the error termoil answered with.
+fn round_trip(socket: &std::ffi::OsStr, request: &str) -> Result<String> {
+    let mut stream = UnixStream::connect(socket)
+        .with_context(|| format!("connecting to {}", PathBuf::from(socket).display()))?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    stream.write_all(format!("{request}\n").as_bytes())?;
+    let mut reply = String::new();
+    BufReader::new(&stream).read_line(&mut reply)?;
+    let reply = reply.trim();
+    if let Some(err) = reply.strip_prefix("error: ") {
+        bail!("termoil: {err}");
+    }
+    Ok(reply.to_string())
+}
+
+/// Sends a report for the pane this process runs in, found through the environment.
+pub fn send(state: ReportedState, message: Option<String>, tool: Option<String>) -> Result<()> {
+    let (socket, pane) = pane_env()?;
+    let request = Request {
+        pane,
+        state,
+        message,
+        tool,
+    };
+    match round_trip(&socket, &serde_json::to_string(&request)?)?.as_str() {
+        "ok" => Ok(()),
+        other => bail!("termoil: unexpected reply: {other}"),
+    }
+}
+
+/// The state of the pane this process runs in, or of every pane when `all` is set.
+pub fn query_state(all: bool) -> Result<Vec<PaneStatus>> {
+    let (socket, pane) = pane_env()?;
+    let mut query = serde_json::json!({ "query": "state" });
+    if !all {
+        query["pane"] = pane.into();
+    }
+    let reply = round_trip(&socket, &query.to_string())?;
+    serde_json::from_str(&reply).context("parsing termoil's reply")
+}
@@ -0,0 +1,229 @@
+//! Terminal emulation behind a trait, so `Pane` and the renderer don't depend on one parser.
+//! vt100 is the default engine; alacritty_terminal (the default `alacritty` feature) also
+//! tracks dim, blink, strikethrough, hidden and hyperlinks.
+
+#[cfg(feature = "alacritty")]
+mod alacritty;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Color {
+    Default,
+    Idx(u8),
+    Rgb(u8, u8, u8),
+}
+
+/// A screen cell, independent of the emulator that produced it, borrowed for the length of a
+/// `visit_row` callback.
+#[derive(Clone, Debug)]
+pub struct Cell<'a> {
+    pub symbol: &'a str,
+    pub fg: Color,
+    pub bg: Color,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub inverse: bool,
+    pub dim: bool,
+    pub blink: bool,
+    pub strikethrough: bool,
+    pub hidden: bool,
+    /// Target of the OSC 8 hyperlink covering this cell.
+    pub hyperlink: Option<&'a str>,
+    /// The left half of a double-width character.
+    pub wide: bool,
+    /// The right half of a double-width character; its symbol lives in the cell before it.
+    pub wide_continuation: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MouseMode {
+    None,
+    Press,
+    PressRelease,
+    ButtonMotion,
+    AnyMotion,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MouseEncoding {
+    Default,
+    Utf8,
+    Sgr,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum EmulatorKind {
+    #[default]
+    Vt100,
+    #[cfg(feature = "alacritty")]
+    Alacritty,
+}
+
+pub trait TerminalEmulator {
+    fn process(&mut self, data: &[u8]);
+    fn resize(&mut self, rows: u16, cols: u16);
+    /// `(row, col)` of the cursor, zero-based.
+    fn cursor_position(&self) -> (u16, u16);
+    fn hide_cursor(&self) -> bool;
+    fn application_cursor(&self) -> bool;
+    fn bracketed_paste(&self) -> bool;
+    fn alternate_screen(&self) -> bool;
+    fn mouse_mode(&self) -> MouseMode;
+    fn mouse_encoding(&self) -> MouseEncoding;
+    /// Calls `visit` with each cell of visible row `row` in columns `start..end` (as far as
+    /// the screen goes), without copying them out.
+    fn visit_row(&self, row: u16, start: u16, end: u16, visit: &mut dyn FnMut(u16, &Cell));
+    /// Text of the visible screen.
+    fn contents(&self) -> String;
+    /// Text of visible rows `start..=end`, one line per row except where a row soft-wraps
+    /// into the next.
+    fn rows_text(&self, start: u16, end: u16) -> String;
+    /// How far the view is scrolled back into history.
+    fn scrollback(&self) -> usize;
+    /// Bells (audible or visual) rung so far; changes with each new one.
+    fn bell_count(&self) -> usize;
+    /// Whether cells carry their OSC 8 hyperlinks.
+    fn keeps_hyperlinks(&self) -> bool;
+}
+
+pub fn new_emulator(
+    kind: EmulatorKind,
+    rows: u16,
+    cols: u16,
+    scrollback: usize,
+) -> Box<dyn TerminalEmulator> {
+    match kind {
+        EmulatorKind::Vt100 => Box::new(Vt100Emulator::new(rows, cols, scrollback)),
+        #[cfg(feature = "alacritty")]
+        EmulatorKind::Alacritty => {
+            Box::new(alacritty::AlacrittyEmulator::new(rows, cols, scrollback))
+        }
+    }
+}
+
+pub struct Vt100Emulator {
+    parser: vt100::Parser,
+}
+
+impl Vt100Emulator {
+    pub fn new(rows: u16, cols: u16, scrollback: usize) -> Self {
+        Self {
+            parser: vt100::Parser::new(rows, cols, scrollback),
+        }
+    }
+}
+
+fn vt100_color(color: vt100::Color) -> Color {
+    match color {
+        vt100::Color::Default => Color::Default,
+        vt100::Color::Idx(idx) => Color::Idx(idx),
+        vt100::Color::Rgb(r, g, b) => Color::Rgb(r, g, b),
+    }
+}
+
+impl TerminalEmulator for Vt100Emulator {
+    fn process(&mut self, data: &[u8]) {
+        self.parser.process(data);
+    }
+
+    fn resize(&mut self, rows: u16, cols: u16) {
+        self.parser.set_size(rows, cols);
+    }
+
+    fn cursor_position(&self) -> (u16, u16) {
+        self.parser.screen().cursor_position()
+    }
+
+    fn hide_cursor(&self) -> bool {
+        self.parser.screen().hide_cursor()
+    }
+
+    fn application_cursor(&self) -> bool {
+        self.parser.screen().application_cursor()
+    }
+
+    fn bracketed_paste(&self) -> bool {
+        self.parser.screen().bracketed_paste()
+    }
+
+    fn alternate_screen(&self) -> bool {
+        self.parser.screen().alternate_screen()
+    }
+
+    fn mouse_mode(&self) -> MouseMode {
+        match self.parser.screen().mouse_protocol_mode() {
+            vt100::MouseProtocolMode::None => MouseMode::None,
+            vt100::MouseProtocolMode::Press => MouseMode::Press,
+            vt100::MouseProtocolMode::PressRelease => MouseMode::PressRelease,
+            vt100::MouseProtocolMode::ButtonMotion => MouseMode::ButtonMotion,
+            vt100::MouseProtocolMode::AnyMotion => MouseMode::AnyMotion,
+        }
+    }
+
+    fn mouse_encoding(&self) -> MouseEncoding {
+        match self.parser.screen().mouse_protocol_encoding() {
+            vt100::MouseProtocolEncoding::Default => MouseEncoding::Default,
+            vt100::MouseProtocolEncoding::Utf8 => MouseEncoding::Utf8,
+            vt100::MouseProtocolEncoding::Sgr => MouseEncoding::Sgr,
+        }
+    }
+
+    fn visit_row(&self, row: u16, start: u16, end: u16, visit: &mut dyn FnMut(u16, &Cell)) {
+        let screen = self.parser.screen();
+        for col in start..end.min(screen.size().1) {
+            let Some(cell) = screen.cell(row, col) else {
+                break;
+            };
+            // vt100 only hands out contents as a new string; blank cells skip it.
+            let symbol = if cell.has_contents() {
+                cell.contents()
+            } else {
+                String::new()
+            };
+            visit(
+                col,
+                &Cell {
+                    symbol: &symbol,
+                    fg: vt100_color(cell.fgcolor()),
+                    bg: vt100_color(cell.bgcolor()),
+                    bold: cell.bold(),
+                    italic: cell.italic(),
+                    underline: cell.underline(),
+                    inverse: cell.inverse(),
+                    dim: false,
+                    blink: false,
+                    strikethrough: false,
+                    hidden: false,
+                    hyperlink: None,
+                    wide: cell.is_wide(),
+                    wide_continuation: cell.is_wide_continuation(),
+                },
+            );
+        }
+    }
+
+    fn contents(&self) -> String {
+        self.parser.screen().contents()
+    }
+
+    fn rows_text(&self, start: u16, end: u16) -> String {
+        let screen = self.parser.screen();
+        let cols = screen.size().1;
+        // `contents_between` only renders the requested rows; `rows()` would build every row
+        // above them too.
+        screen.contents_between(start, 0, end, cols)
+    }
+
+    fn scrollback(&self) -> usize {
+        self.parser.screen().scrollback()
+    }
+
+    fn bell_count(&self) -> usize {
+        let screen = self.parser.screen();
+        screen.audible_bell_count() + screen.visual_bell_count()
+    }
+
+    fn keeps_hyperlinks(&self) -> bool {
+        false
+    }
+}
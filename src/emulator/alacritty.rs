@@ -0,0 +1,346 @@
+use super::{Cell, Color, MouseEncoding, MouseMode, TerminalEmulator};
+use alacritty_terminal::event::{Event, EventListener};
+use alacritty_terminal::grid::Dimensions;
+use alacritty_terminal::index::{Column, Line, Point};
+use alacritty_terminal::term::cell::Flags;
+use alacritty_terminal::term::{Config, Term, TermMode};
+use alacritty_terminal::vte::ansi::{self, NamedColor, Processor};
+use std::rc::Rc;
+
+/// A cell flag alacritty_terminal leaves unused, marking text printed with SGR 5 or 6. The
+/// terminal parses blink but doesn't keep it, so `BlinkScanner` sets this on the cursor's
+/// template and printed cells copy it from there like any other attribute.
+const BLINK: Flags = Flags::from_bits_retain(0x8000);
+
+struct Size {
+    rows: usize,
+    cols: usize,
+}
+
+impl Dimensions for Size {
+    fn total_lines(&self) -> usize {
+        self.rows
+    }
+
+    fn screen_lines(&self) -> usize {
+        self.rows
+    }
+
+    fn columns(&self) -> usize {
+        self.cols
+    }
+}
+
+/// Counts the bells the terminal rings; its other events have no use here.
+#[derive(Clone, Default)]
+struct Bells(Rc<std::cell::Cell<usize>>);
+
+impl EventListener for Bells {
+    fn send_event(&self, event: Event) {
+        if let Event::Bell = event {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+}
+
+/// Finds the SGR sequences that turn blink on or off.
+#[derive(Default)]
+struct BlinkScanner {
+    blink: Option<bool>,
+}
+
+impl vte::Perform for BlinkScanner {
+    fn csi_dispatch(
+        &mut self,
+        params: &vte::Params,
+        intermediates: &[u8],
+        ignore: bool,
+        action: char,
+    ) {
+        if ignore || action != 'm' || !intermediates.is_empty() {
+            return;
+        }
+        if params.is_empty() {
+            self.blink = Some(false);
+        }
+        let mut params = params.iter();
+        while let Some(param) = params.next() {
+            match param.first().copied().unwrap_or(0) {
+                0 | 25 => self.blink = Some(false),
+                5 | 6 => self.blink = Some(true),
+                // `38;5;n` and `38;2;r;g;b` colors, whose 5 isn't blink.
+                38 | 48 | 58 if param.len() == 1 => match params.next().and_then(|p| p.first()) {
+                    Some(5) => {
+                        params.next();
+                    }
+                    Some(2) => {
+                        params.nth(2);
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+    }
+}
+
+pub struct AlacrittyEmulator {
+    term: Term<Bells>,
+    processor: Processor,
+    bells: Bells,
+    sgr: vte::Parser,
+    blink: BlinkScanner,
+}
+
+impl AlacrittyEmulator {
+    pub fn new(rows: u16, cols: u16, scrollback: usize) -> Self {
+        let config = Config {
+            scrolling_history: scrollback,
+            ..Config::default()
+        };
+        let size = Size {
+            rows: rows.max(1) as usize,
+            cols: cols.max(1) as usize,
+        };
+        let bells = Bells::default();
+        Self {
+            term: Term::new(config, &size, bells.clone()),
+            processor: Processor::new(),
+            bells,
+            sgr: vte::Parser::new(),
+            blink: BlinkScanner::default(),
+        }
+    }
+
+    fn advance(&mut self, data: &[u8]) {
+        self.processor.advance(&mut self.term, data);
+        // `Pane` already holds back synchronized updates; don't buffer them a second time.
+        if self.processor.sync_timeout().sync_timeout().is_some() {
+            self.processor.stop_sync(&mut self.term);
+        }
+    }
+
+    fn row_text(&self, row: u16) -> (String, bool) {
+        let grid = self.term.grid();
+        let cols = grid.columns();
+        let mut text = String::with_capacity(cols);
+        let mut wrapped = false;
+        for col in 0..cols {
+            let cell = &grid[Point::new(Line(row as i32), Column(col))];
+            if cell.flags.contains(Flags::WRAPLINE) {
+                wrapped = true;
+            }
+            if cell
+                .flags
+                .intersects(Flags::WIDE_CHAR_SPACER | Flags::LEADING_WIDE_CHAR_SPACER)
+            {
+                continue;
+            }
+            text.push(cell.c);
+            if let Some(zerowidth) = cell.zerowidth() {
+                text.extend(zerowidth);
+            }
+        }
+        if !wrapped {
+            text.truncate(text.trim_end().len());
+        }
+        (text, wrapped)
+    }
+}
+
+fn ansi_color(color: ansi::Color) -> Color {
+    match color {
+        ansi::Color::Spec(rgb) => Color::Rgb(rgb.r, rgb.g, rgb.b),
+        ansi::Color::Indexed(idx) => Color::Idx(idx),
+        ansi::Color::Named(named) => match named {
+            NamedColor::Foreground | NamedColor::Background | NamedColor::BrightForeground => {
+                Color::Default
+            }
+            NamedColor::DimForeground | NamedColor::Cursor => Color::Default,
+            NamedColor::DimBlack => Color::Idx(0),
+            NamedColor::DimRed => Color::Idx(1),
+            NamedColor::DimGreen => Color::Idx(2),
+            NamedColor::DimYellow => Color::Idx(3),
+            NamedColor::DimBlue => Color::Idx(4),
+            NamedColor::DimMagenta => Color::Idx(5),
+            NamedColor::DimCyan => Color::Idx(6),
+            NamedColor::DimWhite => Color::Idx(7),
+            other => Color::Idx(other as u8),
+        },
+    }
+}
+
+impl TerminalEmulator for AlacrittyEmulator {
+    fn process(&mut self, data: &[u8]) {
+        // Feed the terminal up to the end of each blink change, then apply it, so it lands
+        // between the same characters it did in the output.
+        let mut start = 0;
+        for (idx, &byte) in data.iter().enumerate() {
+            self.sgr.advance(&mut self.blink, byte);
+            if let Some(blink) = self.blink.blink.take() {
+                self.advance(&data[start..=idx]);
+                start = idx + 1;
+                self.term.grid_mut().cursor.template.flags.set(BLINK, blink);
+            }
+        }
+        self.advance(&data[start..]);
+    }
+
+    fn resize(&mut self, rows: u16, cols: u16) {
+        self.term.resize(Size {
+            rows: rows.max(1) as usize,
+            cols: cols.max(1) as usize,
+        });
+    }
+
+    fn cursor_position(&self) -> (u16, u16) {
+        let point = self.term.grid().cursor.point;
+        (point.line.0.max(0) as u16, point.column.0 as u16)
+    }
+
+    fn hide_cursor(&self) -> bool {
+        !self.term.mode().contains(TermMode::SHOW_CURSOR)
+    }
+
+    fn application_cursor(&self) -> bool {
+        self.term.mode().contains(TermMode::APP_CURSOR)
+    }
+
+    fn bracketed_paste(&self) -> bool {
+        self.term.mode().contains(TermMode::BRACKETED_PASTE)
+    }
+
+    fn alternate_screen(&self) -> bool {
+        self.term.mode().contains(TermMode::ALT_SCREEN)
+    }
+
+    fn mouse_mode(&self) -> MouseMode {
+        let mode = self.term.mode();
+        if mode.contains(TermMode::MOUSE_MOTION) {
+            MouseMode::AnyMotion
+        } else if mode.contains(TermMode::MOUSE_DRAG) {
+            MouseMode::ButtonMotion
+        } else if mode.contains(TermMode::MOUSE_REPORT_CLICK) {
+            MouseMode::PressRelease
+        } else {
+            MouseMode::None
+        }
+    }
+
+    fn mouse_encoding(&self) -> MouseEncoding {
+        let mode = self.term.mode();
+        if mode.contains(TermMode::SGR_MOUSE) {
+            MouseEncoding::Sgr
+        } else if mode.contains(TermMode::UTF8_MOUSE) {
+            MouseEncoding::Utf8
+        } else {
+            MouseEncoding::Default
+        }
+    }
+
+    fn visit_row(&self, row: u16, start: u16, end: u16, visit: &mut dyn FnMut(u16, &Cell)) {
+        let grid = self.term.grid();
+        if row as usize >= grid.screen_lines() {
+            return;
+        }
+        let mut utf8 = [0u8; 4];
+        for col in start..end.min(grid.columns() as u16) {
+            let cell = &grid[Point::new(Line(row as i32), Column(col as usize))];
+            let combined: String;
+            let symbol = match cell.zerowidth() {
+                Some(zerowidth) => {
+                    combined = std::iter::once(cell.c)
+                        .chain(zerowidth.iter().copied())
+                        .collect();
+                    &combined
+                }
+                None => &*cell.c.encode_utf8(&mut utf8),
+            };
+            // A reference count bump, not a copy of the URI.
+            let hyperlink = cell.hyperlink();
+            visit(
+                col,
+                &Cell {
+                    symbol,
+                    fg: ansi_color(cell.fg),
+                    bg: ansi_color(cell.bg),
+                    bold: cell.flags.contains(Flags::BOLD),
+                    italic: cell.flags.contains(Flags::ITALIC),
+                    underline: cell.flags.intersects(Flags::ALL_UNDERLINES),
+                    inverse: cell.flags.contains(Flags::INVERSE),
+                    dim: cell.flags.contains(Flags::DIM),
+                    blink: cell.flags.contains(BLINK),
+                    strikethrough: cell.flags.contains(Flags::STRIKEOUT),
+                    hidden: cell.flags.contains(Flags::HIDDEN),
+                    hyperlink: hyperlink.as_ref().map(|link| link.uri()),
+                    wide: cell.flags.contains(Flags::WIDE_CHAR),
+                    wide_continuation: cell.flags.contains(Flags::WIDE_CHAR_SPACER),
+                },
+            );
+        }
+    }
+
+    fn contents(&self) -> String {
+        let rows = self.term.screen_lines() as u16;
+        let text = self.rows_text(0, rows.saturating_sub(1));
+        text.trim_end_matches('\n').to_string()
+    }
+
+    fn rows_text(&self, start: u16, end: u16) -> String {
+        let mut text = String::new();
+        for row in start..=end.min((self.term.screen_lines() as u16).saturating_sub(1)) {
+            let (line, wrapped) = self.row_text(row);
+            text.push_str(&line);
+            if !wrapped && row != end {
+                text.push('\n');
+            }
+        }
+        text
+    }
+
+    fn scrollback(&self) -> usize {
+        self.term.grid().display_offset()
+    }
+
+    fn bell_count(&self) -> usize {
+        self.bells.0.get()
+    }
+
+    fn keeps_hyperlinks(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blinking(data: &[&[u8]]) -> String {
+        let mut emulator = AlacrittyEmulator::new(2, 20, 0);
+        for chunk in data {
+            emulator.process(chunk);
+        }
+        let mut marks = String::new();
+        emulator.visit_row(0, 0, 20, &mut |_, cell| {
+            if !cell.symbol.trim().is_empty() {
+                marks.push(if cell.blink { 'B' } else { '.' });
+            }
+        });
+        marks
+    }
+
+    #[test]
+    fn blink_follows_sgr() {
+        assert_eq!(
+            blinking(&[b"a\x1b[5mb\x1b[38;5;1mc\x1b[25md\x1b[1;6me\x1b[mf"]),
+            ".BB.B."
+        );
+        assert_eq!(blinking(&[b"\x1b[5;38;2;5;5;5mab\x1b[0mc"]), "BB.");
+        assert_eq!(blinking(&[b"\x1b[38;5;5mab\x1b[48:5:5mc"]), "...");
+    }
+
+    #[test]
+    fn blink_split_across_reads() {
+        assert_eq!(blinking(&[b"a\x1b[", b"5mb", b"\x1b[2", b"5mc"]), ".B.");
+    }
+}
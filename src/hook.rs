@@ -0,0 +1,160 @@
+//! `termoil hook claude`: turns Claude Code hook payloads into pane state reports.
+
+use crate::control::{self, ReportedState};
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::io::Read;
+
+/// Longest tool summary shown in a pane title.
+const TOOL_SUMMARY_CHARS: usize = 80;
+
+pub const CLAUDE_SETTINGS: &str = r#"{
+  "hooks": {
+    "PreToolUse": [
+      { "matcher": "*", "hooks": [{ "type": "command", "command": "termoil hook claude" }] }
+    ],
+    "Notification": [
+      { "hooks": [{ "type": "command", "command": "termoil hook claude" }] }
+    ],
+    "Stop": [
+      { "hooks": [{ "type": "command", "command": "termoil hook claude" }] }
+    ]
+  }
+}"#;
+
+/// `Bash: cargo test`, from a tool's name and the input field that best describes the call.
+fn tool_summary(payload: &Value) -> Option<String> {
+    let name = payload["tool_name"].as_str()?;
+    let input = &payload["tool_input"];
+    let detail = [
+        "command",
+        "file_path",
+        "url",
+        "pattern",
+        "path",
+        "description",
+    ]
+    .iter()
+    .find_map(|key| input[key].as_str());
+    let summary = match detail {
+        Some(detail) => format!("{}: {}", name, detail.lines().next().unwrap_or("")),
+        None => name.to_string(),
+    };
+    if summary.chars().count() <= TOOL_SUMMARY_CHARS {
+        return Some(summary);
+    }
+    let mut truncated: String = summary.chars().take(TOOL_SUMMARY_CHARS - 1).collect();
+    truncated.push('…');
+    Some(truncated)
+}
+
+/// What a Claude Code hook payload says about the pane, if anything.
+fn claude_report(payload: &Value) -> Option<(ReportedState, Option<String>, Option<String>)> {
+    let message = payload["message"].as_str().map(str::to_string);
+    match payload["hook_event_name"].as_str()? {
+        "PreToolUse" => Some((ReportedState::Working, None, tool_summary(payload))),
+        // Sent both when a tool needs permission and when Claude has sat idle at its prompt.
+        "Notification" => {
+            let permission = message
+                .as_deref()
+                .is_some_and(|message| message.contains("permission"));
+            let state = if permission {
+                ReportedState::Waiting
+            } else {
+                ReportedState::Input
+            };
+            Some((state, message, None))
+        }
+        "Stop" => Some((ReportedState::Idle, None, None)),
+        _ => None,
+    }
+}
+
+/// Reads a Claude Code hook payload from stdin and reports it for this pane. Outside termoil
+/// this does nothing, so the hooks can live in global settings.
+pub fn claude() -> Result<()> {
+    if std::env::var_os(control::SOCKET_ENV).is_none() {
+        return Ok(());
+    }
+    let mut input = String::new();
+    std::io::stdin().read_to_string(&mut input)?;
+    let payload: Value = serde_json::from_str(&input).context("parsing hook payload")?;
+    match claude_report(&payload) {
+        Some((state, message, tool)) => control::send(state, message, tool),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn pre_tool_use(tool: &str, input: Value) -> Value {
+        json!({"hook_event_name": "PreToolUse", "tool_name": tool, "tool_input": input})
+    }
+
+    #[test]
+    fn tool_calls_are_summarized() {
+        let bash = pre_tool_use("Bash", json!({"command": "cargo test\ncargo build"}));
+        assert_eq!(
+            claude_report(&bash),
+            Some((
+                ReportedState::Working,
+                None,
+                Some("Bash: cargo test".into())
+            ))
+        );
+        let edit = pre_tool_use(
+            "Edit",
+            json!({"file_path": "src/hook.rs", "old_string": "a", "new_string": "b"}),
+        );
+        assert_eq!(tool_summary(&edit).as_deref(), Some("Edit: src/hook.rs"));
+        let todo = pre_tool_use("TodoWrite", json!({"todos": []}));
+        assert_eq!(tool_summary(&todo).as_deref(), Some("TodoWrite"));
+    }
+
+    #[test]
+    fn long_tool_summaries_are_truncated() {
+        let command = "é".repeat(100);
+        let summary = tool_summary(&pre_tool_use("Bash", json!({ "command": command }))).unwrap();
+        assert_eq!(summary.chars().count(), TOOL_SUMMARY_CHARS);
+        assert!(summary.starts_with("Bash: éé"));
+        assert!(summary.ends_with("é…"));
+
+        let fits = format!("Bash: {}", "x".repeat(TOOL_SUMMARY_CHARS - 6));
+        let summary = tool_summary(&pre_tool_use("Bash", json!({ "command": &fits[6..] })));
+        assert_eq!(summary, Some(fits));
+    }
+
+    #[test]
+    fn notifications_tell_permission_from_idle() {
+        let permission = "Claude needs your permission to use Bash";
+        let report =
+            claude_report(&json!({"hook_event_name": "Notification", "message": permission}));
+        assert_eq!(
+            report,
+            Some((ReportedState::Waiting, Some(permission.into()), None))
+        );
+
+        let idle = "Claude is waiting for your input";
+        let report = claude_report(&json!({"hook_event_name": "Notification", "message": idle}));
+        assert_eq!(
+            report,
+            Some((ReportedState::Input, Some(idle.into()), None))
+        );
+    }
+
+    #[test]
+    fn stop_and_other_events() {
+        assert_eq!(
+            claude_report(&json!({"hook_event_name": "Stop"})),
+            Some((ReportedState::Idle, None, None))
+        );
+        assert_eq!(
+            claude_report(&json!({"hook_event_name": "SessionStart"})),
+            None
+        );
+        assert_eq!(claude_report(&json!({"message": "no event"})), None);
+    }
+}
@@ -0,0 +1,520 @@
+use crossterm::event::{
+    KeyCode, KeyEvent, KeyEventKind, KeyEventState, KeyModifiers, ModifierKeyCode,
+};
+
+/// Input modes the child application has switched on that change how keys are encoded.
+///
+/// Application keypad mode (DECKPAM) isn't among them: outside the kitty protocol the host
+/// terminal doesn't tell keypad keys apart from the main keyboard, so they're sent as the
+/// characters they type.
+#[derive(Clone, Copy, Default)]
+pub struct KeyModes {
+    pub application_cursor: bool,
+}
+
+/// xterm modifier parameter: 1 + shift(1) + alt(2) + ctrl(4) + meta(8).
+fn modifier_param(modifiers: KeyModifiers) -> u8 {
+    let mut param = 1;
+    if modifiers.contains(KeyModifiers::SHIFT) {
+        param += 1;
+    }
+    if modifiers.contains(KeyModifiers::ALT) {
+        param += 2;
+    }
+    if modifiers.contains(KeyModifiers::CONTROL) {
+        param += 4;
+    }
+    if modifiers.contains(KeyModifiers::META) {
+        param += 8;
+    }
+    param
+}
+
+/// Cursor, Home/End and F1-F4 keys: `SS3 final` in application cursor mode, `CSI final`
+/// otherwise, and `CSI 1 ; mod final` when modified.
+fn cursor_key(final_byte: char, param: u8, application_cursor: bool) -> Vec<u8> {
+    if param > 1 {
+        format!("\x1b[1;{}{}", param, final_byte).into_bytes()
+    } else if application_cursor {
+        format!("\x1bO{}", final_byte).into_bytes()
+    } else {
+        format!("\x1b[{}", final_byte).into_bytes()
+    }
+}
+
+/// Editing and function keys of the form `CSI n ~` / `CSI n ; mod ~`.
+fn tilde_key(number: u8, param: u8) -> Vec<u8> {
+    if param > 1 {
+        format!("\x1b[{};{}~", number, param).into_bytes()
+    } else {
+        format!("\x1b[{}~", number).into_bytes()
+    }
+}
+
+/// The control character Ctrl+`c` sends, if it has one.
+fn control_char(c: char) -> Option<u8> {
+    match c {
+        'a'..='z' => Some(c as u8 - b'a' + 1),
+        'A'..='Z' => Some(c as u8 - b'A' + 1),
+        '@' | ' ' | '2' => Some(0),
+        '[' | '3' => Some(0x1b),
+        '\\' | '4' => Some(0x1c),
+        ']' | '5' => Some(0x1d),
+        '^' | '6' => Some(0x1e),
+        '_' | '/' | '7' => Some(0x1f),
+        '?' | '8' => Some(0x7f),
+        _ => None,
+    }
+}
+
+fn with_alt(mut bytes: Vec<u8>, alt: bool) -> Vec<u8> {
+    if alt {
+        bytes.insert(0, 0x1b);
+    }
+    bytes
+}
+
+/// Encodes a key event the way xterm would send it to the application.
+pub fn encode_key(key: &KeyEvent, modes: KeyModes) -> Option<Vec<u8>> {
+    if key.kind == KeyEventKind::Release {
+        return None;
+    }
+    let modifiers = key.modifiers;
+    let alt = modifiers.intersects(KeyModifiers::ALT | KeyModifiers::META);
+    let ctrl = modifiers.contains(KeyModifiers::CONTROL);
+    let param = modifier_param(modifiers);
+
+    let bytes = match key.code {
+        // Like xterm, Ctrl with a key that has no control character sends the key itself.
+        KeyCode::Char(c) => match control_char(c).filter(|_| ctrl) {
+            Some(byte) => with_alt(vec![byte], alt),
+            None => {
+                let mut buf = [0u8; 4];
+                with_alt(c.encode_utf8(&mut buf).as_bytes().to_vec(), alt)
+            }
+        },
+        KeyCode::Enter => with_alt(vec![b'\r'], alt),
+        KeyCode::Tab if modifiers.contains(KeyModifiers::SHIFT) => b"\x1b[Z".to_vec(),
+        KeyCode::Tab => with_alt(vec![b'\t'], alt),
+        KeyCode::BackTab => b"\x1b[Z".to_vec(),
+        KeyCode::Backspace => with_alt(vec![if ctrl { 0x08 } else { 0x7f }], alt),
+        KeyCode::Esc => with_alt(vec![0x1b], alt),
+        KeyCode::Up => cursor_key('A', param, modes.application_cursor),
+        KeyCode::Down => cursor_key('B', param, modes.application_cursor),
+        KeyCode::Right => cursor_key('C', param, modes.application_cursor),
+        KeyCode::Left => cursor_key('D', param, modes.application_cursor),
+        KeyCode::Home => cursor_key('H', param, modes.application_cursor),
+        KeyCode::End => cursor_key('F', param, modes.application_cursor),
+        KeyCode::Insert => tilde_key(2, param),
+        KeyCode::Delete => tilde_key(3, param),
+        KeyCode::PageUp => tilde_key(5, param),
+        KeyCode::PageDown => tilde_key(6, param),
+        KeyCode::F(n) => match n {
+            1 => cursor_key('P', param, true),
+            2 => cursor_key('Q', param, true),
+            3 => cursor_key('R', param, true),
+            4 => cursor_key('S', param, true),
+            5 => tilde_key(15, param),
+            6 => tilde_key(17, param),
+            7 => tilde_key(18, param),
+            8 => tilde_key(19, param),
+            9 => tilde_key(20, param),
+            10 => tilde_key(21, param),
+            11 => tilde_key(23, param),
+            12 => tilde_key(24, param),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(bytes)
+}
+
+const KITTY_REPORT_EVENT_TYPES: u8 = 0b0000_0010;
+const KITTY_REPORT_ALTERNATE_KEYS: u8 = 0b0000_0100;
+const KITTY_REPORT_ALL_KEYS: u8 = 0b0000_1000;
+
+/// Kitty modifier value: 1 + shift(1) + alt(2) + ctrl(4) + super(8) + hyper(16) + meta(32)
+/// + caps_lock(64) + num_lock(128).
+fn kitty_modifier_param(modifiers: KeyModifiers, state: KeyEventState) -> u16 {
+    let mut bits = 0u16;
+    if modifiers.contains(KeyModifiers::SHIFT) {
+        bits |= 1;
+    }
+    if modifiers.contains(KeyModifiers::ALT) {
+        bits |= 2;
+    }
+    if modifiers.contains(KeyModifiers::CONTROL) {
+        bits |= 4;
+    }
+    if modifiers.contains(KeyModifiers::SUPER) {
+        bits |= 8;
+    }
+    if modifiers.contains(KeyModifiers::HYPER) {
+        bits |= 16;
+    }
+    if modifiers.contains(KeyModifiers::META) {
+        bits |= 32;
+    }
+    if state.contains(KeyEventState::CAPS_LOCK) {
+        bits |= 64;
+    }
+    if state.contains(KeyEventState::NUM_LOCK) {
+        bits |= 128;
+    }
+    bits + 1
+}
+
+fn kitty_keypad_code(code: KeyCode) -> Option<u32> {
+    Some(match code {
+        KeyCode::Char(c @ '0'..='9') => 57399 + (c as u32 - '0' as u32),
+        KeyCode::Char('.') => 57409,
+        KeyCode::Char('/') => 57410,
+        KeyCode::Char('*') => 57411,
+        KeyCode::Char('-') => 57412,
+        KeyCode::Char('+') => 57413,
+        KeyCode::Enter => 57414,
+        KeyCode::Char('=') => 57415,
+        _ => return None,
+    })
+}
+
+fn kitty_modifier_key_code(key: ModifierKeyCode) -> u32 {
+    match key {
+        ModifierKeyCode::LeftShift => 57441,
+        ModifierKeyCode::LeftControl => 57442,
+        ModifierKeyCode::LeftAlt => 57443,
+        ModifierKeyCode::LeftSuper => 57444,
+        ModifierKeyCode::LeftHyper => 57445,
+        ModifierKeyCode::LeftMeta => 57446,
+        ModifierKeyCode::RightShift => 57447,
+        ModifierKeyCode::RightControl => 57448,
+        ModifierKeyCode::RightAlt => 57449,
+        ModifierKeyCode::RightSuper => 57450,
+        ModifierKeyCode::RightHyper => 57451,
+        ModifierKeyCode::RightMeta => 57452,
+        ModifierKeyCode::IsoLevel3Shift => 57453,
+        ModifierKeyCode::IsoLevel5Shift => 57454,
+    }
+}
+
+/// How a key is written in the kitty protocol: `CSI code ... u` or a legacy-style
+/// `CSI number ... ~` / `CSI 1 ... letter` functional key.
+enum KittyKey {
+    Unicode(u32),
+    Tilde(u16),
+    Letter(char),
+}
+
+fn kitty_key(code: KeyCode) -> Option<KittyKey> {
+    Some(match code {
+        KeyCode::Char(c) => KittyKey::Unicode(c.to_lowercase().next().unwrap_or(c) as u32),
+        KeyCode::Enter => KittyKey::Unicode(13),
+        KeyCode::Tab | KeyCode::BackTab => KittyKey::Unicode(9),
+        KeyCode::Backspace => KittyKey::Unicode(127),
+        KeyCode::Esc => KittyKey::Unicode(27),
+        KeyCode::Up => KittyKey::Letter('A'),
+        KeyCode::Down => KittyKey::Letter('B'),
+        KeyCode::Right => KittyKey::Letter('C'),
+        KeyCode::Left => KittyKey::Letter('D'),
+        KeyCode::Home => KittyKey::Letter('H'),
+        KeyCode::End => KittyKey::Letter('F'),
+        KeyCode::Insert => KittyKey::Tilde(2),
+        KeyCode::Delete => KittyKey::Tilde(3),
+        KeyCode::PageUp => KittyKey::Tilde(5),
+        KeyCode::PageDown => KittyKey::Tilde(6),
+        KeyCode::F(1) => KittyKey::Letter('P'),
+        KeyCode::F(2) => KittyKey::Letter('Q'),
+        KeyCode::F(3) => KittyKey::Tilde(13),
+        KeyCode::F(4) => KittyKey::Letter('S'),
+        KeyCode::F(5) => KittyKey::Tilde(15),
+        KeyCode::F(6) => KittyKey::Tilde(17),
+        KeyCode::F(7) => KittyKey::Tilde(18),
+        KeyCode::F(8) => KittyKey::Tilde(19),
+        KeyCode::F(9) => KittyKey::Tilde(20),
+        KeyCode::F(10) => KittyKey::Tilde(21),
+        KeyCode::F(11) => KittyKey::Tilde(23),
+        KeyCode::F(12) => KittyKey::Tilde(24),
+        KeyCode::F(n @ 13..=35) => KittyKey::Unicode(57376 + (n as u32 - 13)),
+        KeyCode::CapsLock => KittyKey::Unicode(57358),
+        KeyCode::ScrollLock => KittyKey::Unicode(57359),
+        KeyCode::NumLock => KittyKey::Unicode(57360),
+        KeyCode::PrintScreen => KittyKey::Unicode(57361),
+        KeyCode::Pause => KittyKey::Unicode(57362),
+        KeyCode::Menu => KittyKey::Unicode(57363),
+        KeyCode::Modifier(m) => KittyKey::Unicode(kitty_modifier_key_code(m)),
+        _ => return None,
+    })
+}
+
+/// Encodes a key event using the kitty keyboard protocol for a child that enabled `flags`.
+/// Keys the protocol leaves unchanged at these flags fall back to `encode_key`.
+pub fn encode_kitty_key(key: &KeyEvent, flags: u8, modes: KeyModes) -> Option<Vec<u8>> {
+    let report_all = flags & KITTY_REPORT_ALL_KEYS != 0;
+    let report_events = flags & KITTY_REPORT_EVENT_TYPES != 0;
+    let event = match key.kind {
+        KeyEventKind::Press => 1,
+        KeyEventKind::Repeat => 2,
+        KeyEventKind::Release => 3,
+    };
+    if event == 3 && !report_events {
+        return None;
+    }
+
+    let mut modifiers = key.modifiers;
+    if key.code == KeyCode::BackTab {
+        modifiers.insert(KeyModifiers::SHIFT);
+    }
+    let text_modifiers = modifiers - KeyModifiers::SHIFT;
+    let is_text_key = matches!(key.code, KeyCode::Char(_)) && text_modifiers.is_empty();
+    let is_legacy_control = matches!(key.code, KeyCode::Enter | KeyCode::Tab | KeyCode::Backspace)
+        && modifiers.is_empty();
+
+    // Plain text and unmodified Enter/Tab/Backspace keep their legacy bytes unless every key
+    // is reported as an escape code; their releases are only reported in that mode too.
+    if !report_all
+        && (is_text_key || is_legacy_control)
+        && !key.state.contains(KeyEventState::KEYPAD)
+    {
+        if event == 3 {
+            return None;
+        }
+        return encode_key(
+            &KeyEvent {
+                kind: KeyEventKind::Press,
+                ..*key
+            },
+            modes,
+        );
+    }
+
+    let kitty = if key.state.contains(KeyEventState::KEYPAD) {
+        kitty_keypad_code(key.code).map(KittyKey::Unicode)
+    } else {
+        None
+    };
+    let kitty = kitty.or_else(|| kitty_key(key.code))?;
+    if matches!(key.code, KeyCode::Modifier(_)) && !report_all {
+        return None;
+    }
+
+    let param = kitty_modifier_param(modifiers, key.state);
+    let mut modifier_field = String::new();
+    if param > 1 || (report_events && event > 1) {
+        modifier_field = param.to_string();
+        if report_events && event > 1 {
+            modifier_field.push_str(&format!(":{}", event));
+        }
+    }
+
+    let bytes = match kitty {
+        KittyKey::Unicode(code) => {
+            let mut key_field = code.to_string();
+            if flags & KITTY_REPORT_ALTERNATE_KEYS != 0 && modifiers.contains(KeyModifiers::SHIFT) {
+                if let KeyCode::Char(c) = key.code {
+                    if c as u32 != code {
+                        key_field.push_str(&format!(":{}", c as u32));
+                    }
+                }
+            }
+            if modifier_field.is_empty() {
+                format!("\x1b[{}u", key_field)
+            } else {
+                format!("\x1b[{};{}u", key_field, modifier_field)
+            }
+        }
+        KittyKey::Tilde(number) => {
+            if modifier_field.is_empty() {
+                format!("\x1b[{}~", number)
+            } else {
+                format!("\x1b[{};{}~", number, modifier_field)
+            }
+        }
+        KittyKey::Letter(letter) => {
+            if modifier_field.is_empty() {
+                format!("\x1b[{}", letter)
+            } else {
+                format!("\x1b[1;{}{}", modifier_field, letter)
+            }
+        }
+    };
+    Some(bytes.into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    fn legacy(code: KeyCode, modifiers: KeyModifiers, application_cursor: bool) -> Vec<u8> {
+        encode_key(&key(code, modifiers), KeyModes { application_cursor }).unwrap()
+    }
+
+    fn kitty(event: KeyEvent, flags: u8) -> Option<Vec<u8>> {
+        encode_kitty_key(&event, flags, KeyModes::default())
+    }
+
+    const NONE: KeyModifiers = KeyModifiers::NONE;
+    const SHIFT: KeyModifiers = KeyModifiers::SHIFT;
+    const ALT: KeyModifiers = KeyModifiers::ALT;
+    const CTRL: KeyModifiers = KeyModifiers::CONTROL;
+    const DISAMBIGUATE: u8 = 0b0000_0001;
+
+    #[test]
+    fn arrows_follow_cursor_mode_until_modified() {
+        assert_eq!(legacy(KeyCode::Up, NONE, false), b"\x1b[A");
+        assert_eq!(legacy(KeyCode::Up, NONE, true), b"\x1bOA");
+        assert_eq!(legacy(KeyCode::Home, NONE, true), b"\x1bOH");
+        for application_cursor in [false, true] {
+            assert_eq!(legacy(KeyCode::Up, CTRL, application_cursor), b"\x1b[1;5A");
+            assert_eq!(
+                legacy(KeyCode::Left, SHIFT | ALT, application_cursor),
+                b"\x1b[1;4D"
+            );
+            assert_eq!(
+                legacy(KeyCode::End, SHIFT, application_cursor),
+                b"\x1b[1;2F"
+            );
+        }
+    }
+
+    #[test]
+    fn function_keys_with_modifiers() {
+        let plain: [&[u8]; 12] = [
+            b"\x1bOP",
+            b"\x1bOQ",
+            b"\x1bOR",
+            b"\x1bOS",
+            b"\x1b[15~",
+            b"\x1b[17~",
+            b"\x1b[18~",
+            b"\x1b[19~",
+            b"\x1b[20~",
+            b"\x1b[21~",
+            b"\x1b[23~",
+            b"\x1b[24~",
+        ];
+        let ctrl: [&[u8]; 12] = [
+            b"\x1b[1;5P",
+            b"\x1b[1;5Q",
+            b"\x1b[1;5R",
+            b"\x1b[1;5S",
+            b"\x1b[15;5~",
+            b"\x1b[17;5~",
+            b"\x1b[18;5~",
+            b"\x1b[19;5~",
+            b"\x1b[20;5~",
+            b"\x1b[21;5~",
+            b"\x1b[23;5~",
+            b"\x1b[24;5~",
+        ];
+        for n in 1..=12u8 {
+            let idx = n as usize - 1;
+            assert_eq!(legacy(KeyCode::F(n), NONE, false), plain[idx], "F{n}");
+            assert_eq!(legacy(KeyCode::F(n), CTRL, false), ctrl[idx], "Ctrl+F{n}");
+        }
+        assert_eq!(legacy(KeyCode::F(1), SHIFT, false), b"\x1b[1;2P");
+        assert_eq!(legacy(KeyCode::F(12), SHIFT | ALT, true), b"\x1b[24;4~");
+    }
+
+    #[test]
+    fn backtab_and_alt_enter() {
+        assert_eq!(legacy(KeyCode::BackTab, SHIFT, false), b"\x1b[Z");
+        assert_eq!(legacy(KeyCode::Tab, SHIFT, false), b"\x1b[Z");
+        assert_eq!(legacy(KeyCode::Enter, ALT, false), b"\x1b\r");
+        assert_eq!(legacy(KeyCode::Enter, NONE, false), b"\r");
+    }
+
+    #[test]
+    fn control_keys() {
+        assert_eq!(legacy(KeyCode::Char('a'), CTRL, false), [0x01]);
+        assert_eq!(legacy(KeyCode::Char('a'), CTRL | ALT, false), [0x1b, 0x01]);
+        assert_eq!(legacy(KeyCode::Char(' '), CTRL, false), [0x00]);
+        assert_eq!(legacy(KeyCode::Char('?'), CTRL, false), [0x7f]);
+        // No control character: the key itself goes through rather than nothing.
+        assert_eq!(legacy(KeyCode::Char(','), CTRL, false), b",");
+        assert_eq!(legacy(KeyCode::Char('1'), CTRL, false), b"1");
+        assert_eq!(legacy(KeyCode::Char('1'), CTRL | ALT, false), b"\x1b1");
+    }
+
+    #[test]
+    fn kitty_disambiguate_keeps_plain_keys_legacy() {
+        assert_eq!(
+            kitty(key(KeyCode::Char('a'), NONE), DISAMBIGUATE).unwrap(),
+            b"a"
+        );
+        assert_eq!(
+            kitty(key(KeyCode::Char('A'), SHIFT), DISAMBIGUATE).unwrap(),
+            b"A"
+        );
+        assert_eq!(
+            kitty(key(KeyCode::Enter, NONE), DISAMBIGUATE).unwrap(),
+            b"\r"
+        );
+        assert_eq!(
+            kitty(key(KeyCode::Char('a'), CTRL), DISAMBIGUATE).unwrap(),
+            b"\x1b[97;5u"
+        );
+        assert_eq!(
+            kitty(key(KeyCode::Esc, NONE), DISAMBIGUATE).unwrap(),
+            b"\x1b[27u"
+        );
+        assert_eq!(
+            kitty(key(KeyCode::Enter, SHIFT), DISAMBIGUATE).unwrap(),
+            b"\x1b[13;2u"
+        );
+        assert_eq!(
+            kitty(key(KeyCode::BackTab, SHIFT), DISAMBIGUATE).unwrap(),
+            b"\x1b[9;2u"
+        );
+        assert_eq!(
+            kitty(key(KeyCode::Up, NONE), DISAMBIGUATE).unwrap(),
+            b"\x1b[A"
+        );
+        assert_eq!(
+            kitty(key(KeyCode::Up, CTRL), DISAMBIGUATE).unwrap(),
+            b"\x1b[1;5A"
+        );
+        assert_eq!(
+            kitty(key(KeyCode::F(5), ALT), DISAMBIGUATE).unwrap(),
+            b"\x1b[15;3~"
+        );
+        let release = KeyEvent::new_with_kind(KeyCode::Char('a'), CTRL, KeyEventKind::Release);
+        assert_eq!(kitty(release, DISAMBIGUATE), None);
+        let shift = KeyCode::Modifier(ModifierKeyCode::LeftShift);
+        assert_eq!(kitty(key(shift, SHIFT), DISAMBIGUATE), None);
+    }
+
+    #[test]
+    fn kitty_report_all_keys() {
+        let flags = DISAMBIGUATE | KITTY_REPORT_ALL_KEYS;
+        assert_eq!(
+            kitty(key(KeyCode::Char('a'), NONE), flags).unwrap(),
+            b"\x1b[97u"
+        );
+        assert_eq!(
+            kitty(key(KeyCode::Char('A'), SHIFT), flags).unwrap(),
+            b"\x1b[97;2u"
+        );
+        assert_eq!(
+            kitty(key(KeyCode::Enter, NONE), flags).unwrap(),
+            b"\x1b[13u"
+        );
+        let shift = KeyCode::Modifier(ModifierKeyCode::LeftShift);
+        assert_eq!(kitty(key(shift, NONE), flags).unwrap(), b"\x1b[57441u");
+
+        let alternates = flags | KITTY_REPORT_ALTERNATE_KEYS;
+        assert_eq!(
+            kitty(key(KeyCode::Char('A'), SHIFT), alternates).unwrap(),
+            b"\x1b[97:65;2u"
+        );
+
+        let events = flags | KITTY_REPORT_EVENT_TYPES;
+        let release = KeyEvent::new_with_kind(KeyCode::Char('a'), NONE, KeyEventKind::Release);
+        assert_eq!(kitty(release, events).unwrap(), b"\x1b[97;1:3u");
+        let repeat = KeyEvent::new_with_kind(KeyCode::Up, CTRL, KeyEventKind::Repeat);
+        assert_eq!(kitty(repeat, events).unwrap(), b"\x1b[1;5:2A");
+    }
+}
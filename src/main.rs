@@ -1,23 +1,73 @@
+mod capture;
+mod config;
+mod control;
+mod emulator;
+mod hook;
+mod keys;
+mod notify;
 mod pty;
+mod sequences;
+mod shell;
+mod state;
 mod ui;
 mod watchdog;
 
-use anyhow::Result;
-use clap::Parser;
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand};
+use config::Config;
+use control::{Report, ReportedState};
 use crossterm::{
+    cursor::{RestorePosition, SavePosition, SetCursorStyle},
     event::{
-        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind, KeyModifiers,
-        MouseEvent, MouseEventKind,
+        self, DisableBracketedPaste, DisableFocusChange, DisableMouseCapture, EnableBracketedPaste,
+        EnableFocusChange, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyEventKind,
+        KeyModifiers, KeyboardEnhancementFlags, MouseEvent, MouseEventKind,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
     },
-    execute,
+    execute, queue,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
+use emulator::{EmulatorKind, MouseEncoding, MouseMode};
+use notify::Notification;
 use pty::Pane;
 use ratatui::prelude::*;
+use shell::Shell;
+use state::{Finished, PaneState};
 use std::io;
-use std::time::Duration;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use ui::GridPreview;
+use watchdog::{Match, Watchdog};
 
-const UI_POLL_MS: u64 = 16;
+const BLINK_INTERVAL_MS: u64 = 400;
+/// How soon to retry flushing input a child hasn't accepted yet.
+const INPUT_RETRY_MS: u64 = 10;
+/// Output parsed per wakeup for the pane the user is looking at, and for every other pane.
+/// Leftover output is picked up on the next wakeup, so a flooding background pane can't
+/// stall input to the selected one.
+const SELECTED_READ_BUDGET: usize = 1024 * 1024;
+const BACKGROUND_READ_BUDGET: usize = 64 * 1024;
+/// Delay before continuing to drain panes that hit their read budget.
+const BACKLOG_RETRY_MS: u64 = 4;
+/// Minimum time between redraws caused only by output in non-selected panes.
+const BACKGROUND_FRAME_MS: u64 = 100;
+/// How often each pane's foreground process is looked up to pick its config profile.
+const PROFILE_CHECK_SECS: u64 = 2;
+/// How long a pane's border flashes in the grid after it rings the bell.
+const BELL_FLASH_MS: u64 = 600;
+
+/// Wakes the main loop: terminal input from the input thread, or a PTY reader that has
+/// new output (or hit EOF) for its pane.
+pub enum AppEvent {
+    Terminal(Event),
+    PtyOutput,
+    /// A child process exited; panes reap theirs on the next pass.
+    ChildExited,
+    Control(control::Request),
+    Query(control::StateQuery),
+}
 
 #[derive(Parser)]
 #[command(
@@ -25,7 +75,91 @@ const UI_POLL_MS: u64 = 16;
     version,
     about = "Less friction for your multi-agent workflow"
 )]
-struct Cli {}
+struct Cli {
+    /// Terminal emulation engine used for panes
+    #[arg(long, global = true, value_enum, default_value_t = EmulatorKind::default())]
+    emulator: EmulatorKind,
+    /// Config file (default: ~/.config/termoil/config.json)
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<PathBuf>,
+    /// How background panes are sized and shown in the grid
+    #[arg(long, value_enum, default_value_t = GridPreview::default())]
+    grid_preview: GridPreview,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Work with watchdog rules
+    Watchdog {
+        #[command(subcommand)]
+        command: WatchdogCommand,
+    },
+    /// Report this pane's state to termoil, from a program running inside it (e.g. an agent
+    /// hook)
+    Notify {
+        #[arg(long, value_enum)]
+        state: ReportedState,
+        /// Shown in the pane title and passed to the notifier
+        #[arg(long)]
+        message: Option<String>,
+        /// The tool call being run or awaiting approval, shown in place of the message
+        #[arg(long)]
+        tool: Option<String>,
+    },
+    /// Print the state of the pane this runs in, as termoil sees it
+    State {
+        /// Every pane, one per line as `<number> <state>`, with `attention` when flagged
+        #[arg(long)]
+        all: bool,
+        /// Print the reply as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Report state from an agent's hooks
+    Hook {
+        #[command(subcommand)]
+        agent: HookCommand,
+    },
+    /// Print the shell integration snippet for a shell, to `eval` from its rc file
+    ShellInit {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+}
+
+#[derive(Subcommand)]
+enum HookCommand {
+    /// Handle a Claude Code hook (PreToolUse, Notification or Stop), reading its JSON payload
+    /// from stdin
+    Claude {
+        /// Print the settings.json snippet that installs the hooks, instead
+        #[arg(long)]
+        print_settings: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum WatchdogCommand {
+    /// Run the configured rules against a captured screen: plain text, ANSI (e.g. from
+    /// `tmux capture-pane -e`) or an asciicast v2 recording
+    Test {
+        file: PathBuf,
+        /// Use this profile's rules
+        #[arg(long)]
+        profile: Option<String>,
+        /// Screen height (default: the recording's, or the capture's line count)
+        #[arg(long)]
+        rows: Option<u16>,
+        /// Screen width (default: the recording's, or the capture's widest line)
+        #[arg(long)]
+        cols: Option<u16>,
+        /// Fail unless the final screen matches this rule (`none` for no match)
+        #[arg(long, value_name = "RULE")]
+        expect: Option<String>,
+    },
+}
 
 fn mouse_modifier_bits(modifiers: KeyModifiers) -> u8 {
     let mut bits = 0;
@@ -42,18 +176,18 @@ fn mouse_modifier_bits(modifiers: KeyModifiers) -> u8 {
 }
 
 fn encode_xterm_mouse(
-    encoding: vt100::MouseProtocolEncoding,
+    encoding: MouseEncoding,
     cb: u8,
     x: u16,
     y: u16,
     release: bool,
 ) -> Option<Vec<u8>> {
     match encoding {
-        vt100::MouseProtocolEncoding::Sgr => {
+        MouseEncoding::Sgr => {
             let suffix = if release { 'm' } else { 'M' };
             Some(format!("\x1b[<{};{};{}{}", cb, x, y, suffix).into_bytes())
         }
-        vt100::MouseProtocolEncoding::Default | vt100::MouseProtocolEncoding::Utf8 => {
+        MouseEncoding::Default | MouseEncoding::Utf8 => {
             let cb_enc = cb.checked_add(32)?;
             let x_enc = u8::try_from(x).ok()?.checked_add(32)?;
             let y_enc = u8::try_from(y).ok()?.checked_add(32)?;
@@ -69,88 +203,526 @@ fn zoom_inner_size(term_h: u16, term_w: u16) -> (u16, u16) {
     )
 }
 
+/// What changed during one `read_pty_output` pass.
+#[derive(Default)]
+struct OutputActivity {
+    selected: bool,
+    background: bool,
+    backlog: bool,
+    /// A pane's state or attention flag changed, e.g. when a timer ran out.
+    states: bool,
+}
+
+/// A pane and everything termoil tracks about it: the state it's shown in, the attention it's
+/// asking for and what it has reported. Kept together so closing or restarting a pane can't
+/// leave any of it behind.
+pub struct PaneSlot {
+    pub pane: Pane,
+    /// Stable id handed to the child as `TERMOIL_PANE_ID`, unlike the pane's index, which
+    /// shifts when panes close.
+    id: u32,
+    pub state: PaneState,
+    pub attention: bool,
+    attention_since: Option<Instant>,
+    /// When a flagged pane stopped looking like it's waiting; the flag is held until
+    /// `attention_clear` has passed.
+    attention_lost: Option<Instant>,
+    /// Whether the pane has waited past the escalation threshold.
+    escalated: bool,
+    acknowledged_generation: Option<u64>,
+    watched_generation: Option<u64>,
+    /// The watchdog's reading of the screen, before exit status and acknowledgement.
+    screen_state: PaneState,
+    /// The watchdog rule behind a waiting state, if any.
+    matched: Option<Match>,
+    /// The config profile, picked by the foreground process.
+    pub profile: Option<usize>,
+    profile_checked: Option<Instant>,
+    /// What the pane last reported over the control socket.
+    report: Option<Report>,
+    /// The long command the pane last finished, until the user gets back to it.
+    pub finished: Option<Finished>,
+    /// When the pane started working, to time commands in shells without OSC 133 marks.
+    working_since: Option<Instant>,
+    /// When the pane last rang the bell, until the user gets back to it.
+    bell: Option<Instant>,
+    /// Whether the border is flashing for a bell.
+    pub bell_flashing: bool,
+    /// Whether the user has turned the pane's bell off.
+    pub bell_muted: bool,
+}
+
+impl PaneSlot {
+    fn new(pane: Pane, id: u32) -> Self {
+        Self {
+            pane,
+            id,
+            state: PaneState::Starting,
+            attention: false,
+            attention_since: None,
+            attention_lost: None,
+            escalated: false,
+            acknowledged_generation: None,
+            watched_generation: None,
+            screen_state: PaneState::Starting,
+            matched: None,
+            profile: None,
+            profile_checked: None,
+            report: None,
+            finished: None,
+            working_since: None,
+            bell: None,
+            bell_flashing: false,
+            bell_muted: false,
+        }
+    }
+
+    /// Replaces the pane with a freshly spawned one. Everything about the old program is
+    /// dropped, but the slot keeps its id, which the new shell was spawned with and programs
+    /// use to report to it, and the user's choice to mute its bell.
+    fn restart(&mut self, pane: Pane) {
+        let fresh = Self {
+            bell_muted: self.bell_muted,
+            ..Self::new(pane, self.id)
+        };
+        std::mem::replace(self, fresh).pane.terminate();
+    }
+
+    /// Forgets the finished command once the user is back at it. Input also restarts the
+    /// clock on the command about to run, rather than counting the time spent typing it.
+    fn clear_finished(&mut self) {
+        self.finished = None;
+        self.working_since = None;
+    }
+
+    /// The tool call the pane reported with its current state, or failing that the message,
+    /// while that state holds.
+    pub fn report_detail(&self) -> Option<&str> {
+        let report = self.report.as_ref()?;
+        if self.state != report.state {
+            return None;
+        }
+        report.tool.as_deref().or(report.message.as_deref())
+    }
+
+    fn set_report(
+        &mut self,
+        reported: ReportedState,
+        message: Option<String>,
+        tool: Option<String>,
+    ) {
+        // A request for approval usually follows the report of the tool call it's about.
+        let previous = self.report.take();
+        self.report = reported.pane_state().map(|state| Report {
+            state,
+            message,
+            tool: tool.or_else(|| {
+                previous
+                    .filter(|previous| state.needs_attention() && !previous.state.needs_attention())
+                    .and_then(|previous| previous.tool)
+            }),
+        });
+    }
+
+    /// The watchdog match that put the pane in its current state, while that state holds.
+    pub fn current_match(&self) -> Option<&Match> {
+        self.matched
+            .as_ref()
+            .filter(|found| found.state == self.state)
+    }
+
+    /// How long the pane has been in the attention queue.
+    pub fn waiting_for(&self) -> Option<Duration> {
+        self.attention_since.map(|since| since.elapsed())
+    }
+
+    /// Whether the pane rang the bell and the user hasn't been back to it since.
+    pub fn bell_rang(&self) -> bool {
+        self.bell.is_some()
+    }
+
+    pub fn is_escalated(&self) -> bool {
+        self.escalated
+    }
+}
+
 pub struct App {
-    pub panes: Vec<Pane>,
+    pub panes: Vec<PaneSlot>,
     pub selected: usize,
     pub zoomed: bool,
     pub mouse_capture_enabled: bool,
-    pub watchdog: watchdog::Watchdog,
-    pub attention: Vec<bool>,
-    pub tick: u64,
+    pub watchdog: Watchdog,
+    /// Each profile's rule set, layered over `watchdog`.
+    profile_watchdogs: Vec<Watchdog>,
+    pub config: Config,
     pub scroll_offset: u16,
-    attention_since: Vec<Option<u64>>,
-    acknowledged_generation: Vec<Option<u64>>,
+    pub grid_preview: GridPreview,
+    keyboard_enhancement_supported: bool,
+    host_keyboard_flags: u8,
+    host_cursor_style: u8,
+    host_focused: bool,
+    emulator: EmulatorKind,
+    started: Instant,
+    events_tx: Sender<AppEvent>,
+    events_rx: Receiver<AppEvent>,
+    /// Waiting for the main loop to deliver them through the notifier.
+    notifications: Vec<Notification>,
+    next_pane_id: u32,
+    control_socket: Option<PathBuf>,
 }
 
 impl App {
     fn new() -> Self {
+        let (events_tx, events_rx) = mpsc::channel();
         Self {
             panes: Vec::new(),
             selected: 0,
             zoomed: false,
             mouse_capture_enabled: true,
-            watchdog: watchdog::Watchdog::new(),
-            attention: Vec::new(),
-            tick: 0,
+            watchdog: Watchdog::new(),
+            profile_watchdogs: Vec::new(),
+            config: Config::default(),
             scroll_offset: 0,
-            attention_since: Vec::new(),
-            acknowledged_generation: Vec::new(),
+            grid_preview: GridPreview::default(),
+            keyboard_enhancement_supported: false,
+            host_keyboard_flags: 0,
+            host_cursor_style: 0,
+            host_focused: true,
+            emulator: EmulatorKind::default(),
+            started: Instant::now(),
+            events_tx,
+            events_rx,
+            notifications: Vec::new(),
+            next_pane_id: 1,
+            control_socket: None,
+        }
+    }
+
+    /// Environment for a pane's child, so it can reach the control socket.
+    fn pane_env(&self, id: u32) -> Vec<(&'static str, String)> {
+        let mut env = vec![(control::PANE_ENV, id.to_string())];
+        if let Some(socket) = &self.control_socket {
+            env.push((control::SOCKET_ENV, socket.display().to_string()));
         }
+        env
     }
 
     fn spawn_shell(&mut self, rows: u16, cols: u16) -> Result<()> {
-        let pane = Pane::spawn_shell(rows, cols)?;
-        self.panes.push(pane);
-        self.attention.push(false);
-        self.attention_since.push(None);
-        self.acknowledged_generation.push(None);
+        let id = self.next_pane_id;
+        let env = self.pane_env(id);
+        let pane = Pane::spawn_shell(rows, cols, self.emulator, &env, self.events_tx.clone())?;
+        self.next_pane_id += 1;
+        self.panes.push(PaneSlot::new(pane, id));
         self.selected = self.panes.len() - 1;
         Ok(())
     }
 
-    fn read_pty_output(&mut self) {
-        self.tick = self.tick.wrapping_add(1);
-        for pane in &mut self.panes {
-            pane.read_available();
+    /// Drains PTY output into every pane, within per-pane budgets, and re-evaluates
+    /// attention.
+    fn read_pty_output(&mut self) -> OutputActivity {
+        let mut activity = OutputActivity::default();
+        let mut printed_reports = Vec::new();
+        let mut finished = Vec::new();
+        let mut rang = Vec::new();
+        for (i, slot) in self.panes.iter_mut().enumerate() {
+            let pane = &mut slot.pane;
+            let is_selected = i == self.selected;
+            let budget = if is_selected {
+                SELECTED_READ_BUDGET
+            } else {
+                BACKGROUND_READ_BUDGET
+            };
+            let before = pane.output_generation();
+            activity.backlog |= pane.read_available(budget);
+            printed_reports.extend(
+                pane.take_reports()
+                    .into_iter()
+                    .map(|(state, message)| (i, state, message)),
+            );
+            finished.extend(pane.take_finished().into_iter().map(|done| (i, done)));
+            if pane.take_bell() {
+                rang.push(i);
+            }
+            if pane.output_generation() != before {
+                if is_selected {
+                    activity.selected = true;
+                } else {
+                    activity.background = true;
+                }
+            }
         }
-        if self.zoomed {
-            return;
+        for (i, state, message) in printed_reports {
+            self.panes[i].set_report(state, message, None);
         }
-        for (i, pane) in self.panes.iter().enumerate() {
-            let raw_attention = self
-                .watchdog
-                .needs_attention(&pane.cursor_line(), &pane.lines_near_cursor());
+        let escalate_after = self.config.escalate_after();
+        let finished_after = self.config.finished_after();
+        for i in rang {
+            let slot = &mut self.panes[i];
+            if slot.bell_muted || self.zoomed && i == self.selected {
+                continue;
+            }
+            if slot.bell.is_none() && !slot.attention {
+                self.notifications.push(Notification::bell(i, slot.state));
+            }
+            slot.bell = Some(Instant::now());
+        }
+        for (i, slot) in self.panes.iter_mut().enumerate() {
+            let pane = &slot.pane;
+            let profile_due = slot
+                .profile_checked
+                .is_none_or(|checked| checked.elapsed() >= Duration::from_secs(PROFILE_CHECK_SECS));
+            if profile_due {
+                let profile = pane
+                    .foreground_command()
+                    .and_then(|command| self.config.profile_for(&command));
+                if profile != slot.profile {
+                    // A different rule set may read the same screen differently.
+                    slot.watched_generation = None;
+                }
+                slot.profile = profile;
+                slot.profile_checked = Some(Instant::now());
+            }
+            // The screen only changes with output, so reuse the last verdict until it does.
             let generation = pane.output_generation();
-            let suppressed =
-                self.acknowledged_generation.get(i).copied().flatten() == Some(generation);
-            let effective_attention = raw_attention && !suppressed;
+            if slot.watched_generation != Some(generation) {
+                let watchdog = slot
+                    .profile
+                    .and_then(|profile| self.profile_watchdogs.get(profile))
+                    .unwrap_or(&self.watchdog);
+                let (state, found) = watchdog.classify(&pane.watch_context(watchdog.reach()));
+                slot.screen_state = state;
+                slot.matched = found;
+                slot.watched_generation = Some(generation);
+            }
+            let before_state = slot.state;
+            let reported = slot.report.as_ref().map(|report| report.state);
+            let stalled = self
+                .config
+                .stall_after(slot.profile)
+                .is_some_and(|after| pane.since_output() >= after);
+            let state = match (pane.exit_code(), reported) {
+                (Some(0), _) => PaneState::Exited,
+                (Some(_), _) => PaneState::Errored,
+                // A pane that says it's working can still hang, e.g. in a stuck tool call.
+                (None, Some(PaneState::Working))
+                    if stalled && !slot.screen_state.needs_attention() =>
+                {
+                    PaneState::Stalled
+                }
+                // The pane's own word wins, though the watchdog still catches prompts while
+                // it claims to be working or idle.
+                (None, Some(reported))
+                    if reported.needs_attention() || !slot.screen_state.needs_attention() =>
+                {
+                    reported
+                }
+                (None, _) if generation == 0 => PaneState::Starting,
+                (None, _) if slot.screen_state == PaneState::Working && stalled => {
+                    PaneState::Stalled
+                }
+                (None, _) => slot.screen_state,
+            };
+            let raw_attention = state.needs_attention();
+            let suppressed = slot.acknowledged_generation == Some(generation);
+            // Raise only once the waiting screen has held still, and once raised, hold the
+            // flag (and the state behind it) until the pane has looked settled for a while.
+            let raised = slot.attention_since.is_some();
+            let held = raised && !raw_attention && {
+                let lost = *slot.attention_lost.get_or_insert_with(Instant::now);
+                lost.elapsed() < self.config.attention_clear()
+            };
+            if raw_attention {
+                slot.attention_lost = None;
+            }
+            // Reports are deliberate, so they skip the wait for a stable screen.
+            let settling = raw_attention
+                && !raised
+                && reported != Some(state)
+                && pane.since_output() < self.config.attention_stable();
+            if !held && !settling {
+                slot.state = state;
+            }
+            let flagged = !suppressed && (held || raw_attention && !settling);
+            // No need to notify about the pane the user is looking at.
+            let viewed = self.zoomed && i == self.selected;
+            if viewed {
+                slot.bell = None;
+            }
+            let effective_attention = flagged || slot.bell.is_some();
+            let flashing = slot
+                .bell
+                .is_some_and(|rang| rang.elapsed() < Duration::from_millis(BELL_FLASH_MS));
+            activity.states |= slot.bell_flashing != flashing;
+            slot.bell_flashing = flashing;
 
-            self.attention[i] = effective_attention;
+            // Without shell marks, a command is taken to run from when the pane starts working
+            // until it's back at a prompt.
+            match slot.state {
+                PaneState::Working | PaneState::Stalled => {
+                    slot.working_since.get_or_insert_with(Instant::now);
+                    slot.finished = None;
+                }
+                PaneState::Idle if !slot.pane.has_shell_marks() => {
+                    if let Some(since) = slot.working_since.take() {
+                        let took = since.elapsed();
+                        finished.push((i, Finished { exit: None, took }));
+                    }
+                }
+                _ => slot.working_since = None,
+            }
+
+            activity.states |= slot.state != before_state || slot.attention != effective_attention;
+            slot.attention = effective_attention;
             if effective_attention {
-                if self.attention_since[i].is_none() {
-                    self.attention_since[i] = Some(self.tick);
+                if slot.attention_since.is_none() {
+                    // A bell has already sent its own notification.
+                    if !viewed && flagged {
+                        self.notifications.push(Notification::attention(
+                            i,
+                            slot.state,
+                            slot.report_detail(),
+                        ));
+                    }
+                    slot.attention_since = Some(Instant::now());
+                }
+                let waited = slot.waiting_for().unwrap_or_default();
+                if flagged && escalate_after.is_some_and(|after| waited >= after) && !slot.escalated
+                {
+                    slot.escalated = true;
+                    if !viewed {
+                        self.notifications
+                            .push(Notification::escalation(i, slot.state, waited));
+                    }
                 }
             } else {
-                self.attention_since[i] = None;
+                slot.attention_since = None;
+                slot.attention_lost = None;
+                slot.escalated = false;
                 if !raw_attention {
-                    self.acknowledged_generation[i] = None;
+                    slot.acknowledged_generation = None;
+                }
+            }
+        }
+        for (i, done) in finished {
+            if finished_after.is_some_and(|after| done.took >= after) {
+                self.panes[i].finished = Some(done);
+                activity.states = true;
+                if !(self.zoomed && i == self.selected) {
+                    self.notifications.push(Notification::finished(i, done));
+                }
+            }
+        }
+        activity
+    }
+
+    /// Answers a `state` query: the pane with id `pane`, or every pane.
+    fn pane_statuses(&self, pane: Option<u32>) -> Vec<control::PaneStatus> {
+        self.panes
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| pane.is_none_or(|pane| pane == slot.id))
+            .map(|(idx, slot)| control::PaneStatus {
+                pane: slot.id,
+                number: idx + 1,
+                state: slot.state.label().to_string(),
+                attention: slot.attention,
+            })
+            .collect()
+    }
+
+    /// Applies a report sent over the control socket; `clear` hands the pane back to the
+    /// watchdog.
+    fn apply_report(&mut self, request: control::Request) {
+        let Some(slot) = self.panes.iter_mut().find(|slot| slot.id == request.pane) else {
+            return;
+        };
+        slot.set_report(request.state, request.message, request.tool);
+    }
+
+    pub fn blink_on(&self) -> bool {
+        let ticks = self.started.elapsed().as_millis() as u64 / BLINK_INTERVAL_MS;
+        ticks.is_multiple_of(2)
+    }
+
+    /// How long the main loop may sleep before a timer needs servicing, or `None` to wait
+    /// for the next event.
+    fn next_wakeup(&self) -> Option<Duration> {
+        let mut wakeup = None;
+        if self.panes.iter().any(|slot| slot.pane.has_pending_input()) {
+            wakeup = earliest(wakeup, Duration::from_millis(INPUT_RETRY_MS));
+        }
+        if self.panes.iter().any(|slot| slot.attention) {
+            let elapsed = self.started.elapsed().as_millis() as u64;
+            let next_toggle = BLINK_INTERVAL_MS - elapsed % BLINK_INTERVAL_MS;
+            wakeup = earliest(wakeup, Duration::from_millis(next_toggle));
+        }
+        // Wake when a working pane would cross its stall threshold, or its profile (and so
+        // its threshold) may have changed.
+        for slot in &self.panes {
+            let since_output = slot.pane.since_output();
+            if slot.state == PaneState::Working {
+                if let Some(stall_after) = self.config.stall_after(slot.profile) {
+                    if since_output < stall_after {
+                        wakeup = earliest(wakeup, stall_after - since_output);
+                    }
+                }
+                if let Some(checked) = slot.profile_checked {
+                    let recheck = Duration::from_secs(PROFILE_CHECK_SECS);
+                    wakeup = earliest(wakeup, recheck.saturating_sub(checked.elapsed()));
                 }
             }
         }
+        // Wake when a waiting screen has held still long enough to flag, or a held flag
+        // is due to clear.
+        for slot in &self.panes {
+            let stable = self.config.attention_stable();
+            if slot.screen_state.needs_attention() && slot.pane.since_output() < stable {
+                wakeup = earliest(wakeup, stable.saturating_sub(slot.pane.since_output()));
+            }
+            if let Some(lost) = slot.attention_lost {
+                let clear = self.config.attention_clear();
+                wakeup = earliest(wakeup, clear.saturating_sub(lost.elapsed()));
+            }
+        }
+        for rang in self.panes.iter().filter_map(|slot| slot.bell) {
+            let flash = Duration::from_millis(BELL_FLASH_MS);
+            if rang.elapsed() < flash {
+                wakeup = earliest(wakeup, flash - rang.elapsed());
+            }
+        }
+        // Synchronized updates that never end are shown once they time out.
+        for deadline in self
+            .panes
+            .iter()
+            .filter_map(|slot| slot.pane.sync_deadline())
+        {
+            wakeup = earliest(wakeup, deadline.saturating_duration_since(Instant::now()));
+        }
+        wakeup
     }
 
+    /// Sizes every PTY for the grid view: its own grid cell, or the zoomed size when the
+    /// grid previews full-size panes, so toggling zoom doesn't resize anything.
     fn resize_all_to_grid(&mut self, term_h: u16, term_w: u16) {
         if self.panes.is_empty() {
             return;
         }
+        if self.grid_preview != GridPreview::Resize {
+            let (rows, cols) = zoom_inner_size(term_h, term_w);
+            for slot in &mut self.panes {
+                slot.pane.resize(rows, cols);
+            }
+            return;
+        }
         let main_h = term_h.saturating_sub(1);
         let areas = ui::compute_pane_areas(
             Rect::new(0, 7, term_w, main_h.saturating_sub(7)),
             self.panes.len(),
         );
-        for (i, pane) in self.panes.iter_mut().enumerate() {
+        for (i, slot) in self.panes.iter_mut().enumerate() {
             if let Some(area) = areas.get(i) {
-                pane.resize(area.height.saturating_sub(2), area.width.saturating_sub(2));
+                slot.pane
+                    .resize(area.height.saturating_sub(2), area.width.saturating_sub(2));
             }
         }
     }
@@ -159,6 +731,9 @@ impl App {
         if self.panes.is_empty() {
             return (24, 80);
         }
+        if self.grid_preview != GridPreview::Resize {
+            return zoom_inner_size(term_h, term_w);
+        }
         let main_h = term_h.saturating_sub(1);
         let areas = ui::compute_pane_areas(
             Rect::new(0, 7, term_w, main_h.saturating_sub(7)),
@@ -179,14 +754,7 @@ impl App {
             return;
         }
 
-        if let Some(pane) = self.panes.get(self.selected) {
-            pane.terminate();
-        }
-
-        self.panes.remove(self.selected);
-        self.attention.remove(self.selected);
-        self.attention_since.remove(self.selected);
-        self.acknowledged_generation.remove(self.selected);
+        self.panes.remove(self.selected).pane.terminate();
 
         if self.panes.is_empty() {
             self.selected = 0;
@@ -204,34 +772,21 @@ impl App {
 
         let idx = self.selected;
         let (rows, cols) = self.selected_grid_inner_size(term_h, term_w);
-        if let Ok(new_pane) = Pane::spawn_shell(rows, cols) {
-            if let Some(old_pane) = self.panes.get(idx) {
-                old_pane.terminate();
-            }
-            self.panes[idx] = new_pane;
-            self.attention[idx] = false;
-            self.attention_since[idx] = None;
-            self.acknowledged_generation[idx] = None;
+        let env = self.pane_env(self.panes[idx].id);
+        if let Ok(new_pane) =
+            Pane::spawn_shell(rows, cols, self.emulator, &env, self.events_tx.clone())
+        {
+            self.panes[idx].restart(new_pane);
         }
     }
 
     pub fn attention_queue(&self) -> Vec<usize> {
-        let mut queue: Vec<(u64, usize)> = self
-            .attention
+        let mut queue: Vec<(Instant, usize)> = self
+            .panes
             .iter()
             .enumerate()
-            .filter_map(|(idx, needs_attention)| {
-                if !*needs_attention {
-                    return None;
-                }
-                let since = self
-                    .attention_since
-                    .get(idx)
-                    .copied()
-                    .flatten()
-                    .unwrap_or(self.tick);
-                Some((since, idx))
-            })
+            .filter(|(_, slot)| slot.attention)
+            .map(|(idx, slot)| (slot.attention_since.unwrap_or_else(Instant::now), idx))
             .collect();
         queue.sort_by_key(|(since, idx)| (*since, *idx));
         queue.into_iter().map(|(_, idx)| idx).collect()
@@ -262,13 +817,25 @@ impl App {
     }
 
     fn acknowledge_selected_attention(&mut self) {
-        if self.panes.is_empty() || self.selected >= self.panes.len() {
+        let Some(slot) = self.panes.get_mut(self.selected) else {
             return;
-        }
-        let generation = self.panes[self.selected].output_generation();
-        self.acknowledged_generation[self.selected] = Some(generation);
-        self.attention[self.selected] = false;
-        self.attention_since[self.selected] = None;
+        };
+        slot.acknowledged_generation = Some(slot.pane.output_generation());
+        slot.attention = false;
+        slot.attention_since = None;
+        slot.attention_lost = None;
+        slot.escalated = false;
+        slot.finished = None;
+        slot.bell = None;
+    }
+
+    /// Stops (or resumes) treating the selected pane's bell as a call for attention.
+    fn toggle_selected_bell(&mut self) {
+        let Some(slot) = self.panes.get_mut(self.selected) else {
+            return;
+        };
+        slot.bell_muted = !slot.bell_muted;
+        slot.bell = None;
     }
 
     fn navigate(&mut self, direction: KeyCode) {
@@ -298,64 +865,197 @@ impl App {
         }
     }
 
-    fn send_key(&mut self, key: KeyCode, modifiers: KeyModifiers) {
+    fn send_key(&mut self, key: &KeyEvent) {
         if self.panes.is_empty() || !self.zoomed {
             return;
         }
-        let pane = &mut self.panes[self.selected];
-        let bytes: Vec<u8> = match key {
-            KeyCode::Char(c) => {
-                if modifiers.contains(KeyModifiers::CONTROL) {
-                    vec![(c as u8) & 0x1f]
-                } else {
-                    c.to_string().into_bytes()
-                }
-            }
-            KeyCode::Enter => vec![b'\r'],
-            KeyCode::Backspace => vec![127],
-            KeyCode::Tab => vec![b'\t'],
-            KeyCode::Up => vec![27, 91, 65],
-            KeyCode::Down => vec![27, 91, 66],
-            KeyCode::Right => vec![27, 91, 67],
-            KeyCode::Left => vec![27, 91, 68],
-            KeyCode::Esc => vec![27],
-            KeyCode::PageUp => vec![27, 91, 53, 126],
-            KeyCode::PageDown => vec![27, 91, 54, 126],
-            KeyCode::Home => vec![27, 91, 72],
-            KeyCode::End => vec![27, 91, 70],
-            KeyCode::Delete => vec![27, 91, 51, 126],
-            _ => return,
+        // Typing answers whatever the pane reported; from here the watchdog takes over.
+        let slot = &mut self.panes[self.selected];
+        slot.report = None;
+        slot.clear_finished();
+        let pane = &mut slot.pane;
+        let modes = keys::KeyModes {
+            application_cursor: pane.application_cursor(),
+        };
+        let kitty_flags = pane.kitty_flags();
+        let bytes = if kitty_flags != 0 {
+            keys::encode_kitty_key(key, kitty_flags, modes)
+        } else {
+            keys::encode_key(key, modes)
         };
-        let _ = pane.write_bytes(&bytes);
+        if let Some(bytes) = bytes {
+            let _ = pane.write_bytes(&bytes);
+        }
+    }
+
+    /// The selected pane has focus while the host terminal does; every other pane is told it
+    /// lost focus.
+    fn sync_focus(&mut self) {
+        let host_focused = self.host_focused;
+        let selected = self.selected;
+        for (i, slot) in self.panes.iter_mut().enumerate() {
+            slot.pane.set_focused(host_focused && i == selected);
+        }
+    }
+
+    fn paste(&mut self, text: &str) {
+        if self.panes.is_empty() || !self.zoomed {
+            return;
+        }
+        let slot = &mut self.panes[self.selected];
+        slot.report = None;
+        slot.clear_finished();
+        let _ = slot.pane.paste(text);
     }
 }
 
+fn run_watchdog_command(
+    command: WatchdogCommand,
+    emulator: EmulatorKind,
+    config: &Config,
+    watchdog: Watchdog,
+    mut profile_watchdogs: Vec<Watchdog>,
+) -> Result<()> {
+    let WatchdogCommand::Test {
+        file,
+        profile,
+        rows,
+        cols,
+        expect,
+    } = command;
+    let watchdog = match profile {
+        Some(name) => {
+            let Some(idx) = config.profiles.iter().position(|p| p.name == name) else {
+                bail!("no profile named `{name}`");
+            };
+            profile_watchdogs.swap_remove(idx)
+        }
+        None => watchdog,
+    };
+    let capture = capture::Capture::load(&file, rows, cols)?;
+    let found = capture::test(&watchdog, emulator, &capture, &mut io::stdout())?;
+    if let Some(expect) = expect {
+        let matched = found.as_ref().map_or("none", |found| found.rule.as_str());
+        if matched != expect {
+            bail!("expected rule `{expect}`, matched `{matched}`");
+        }
+    }
+    Ok(())
+}
+
 fn main() -> Result<()> {
-    Cli::parse();
+    let cli = Cli::parse();
+    if let Some(Command::Notify {
+        state,
+        message,
+        tool,
+    }) = cli.command
+    {
+        return control::send(state, message, tool);
+    }
+    if let Some(Command::State { all, json }) = cli.command {
+        let statuses = control::query_state(all)?;
+        if json {
+            println!("{}", serde_json::to_string(&statuses)?);
+        } else if all {
+            for status in &statuses {
+                let flag = if status.attention { " attention" } else { "" };
+                println!("{} {}{}", status.number, status.state, flag);
+            }
+        } else if let Some(status) = statuses.first() {
+            println!("{}", status.state);
+        }
+        return Ok(());
+    }
+    if let Some(Command::Hook {
+        agent: HookCommand::Claude { print_settings },
+    }) = cli.command
+    {
+        if print_settings {
+            println!("{}", hook::CLAUDE_SETTINGS);
+            return Ok(());
+        }
+        return hook::claude();
+    }
+    if let Some(Command::ShellInit { shell }) = cli.command {
+        print!("{}", shell.init_script());
+        return Ok(());
+    }
+    let config = Config::load(cli.config.as_deref())?;
+    let watchdog = Watchdog::new().with_rules(&config.rules, &config.disable_rules)?;
+    let profile_watchdogs = config
+        .profiles
+        .iter()
+        .map(|profile| {
+            watchdog
+                .clone()
+                .with_rules(&profile.rules, &profile.disable_rules)
+                .with_context(|| format!("profile `{}`", profile.name))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if let Some(Command::Watchdog { command }) = cli.command {
+        return run_watchdog_command(command, cli.emulator, &config, watchdog, profile_watchdogs);
+    }
+    let mut app = App::new();
+    // Without the socket, panes still work; only `termoil notify` is unavailable.
+    let control_server = control::listen(app.events_tx.clone()).ok();
+    pty::watch_child_exits(app.events_tx.clone())?;
+    app.control_socket = control_server.as_ref().map(|server| server.path.clone());
 
     let original_hook = std::panic::take_hook();
     std::panic::set_hook(Box::new(move |panic_info| {
+        let _ = execute!(
+            io::stdout(),
+            PopKeyboardEnhancementFlags,
+            SetCursorStyle::DefaultUserShape
+        );
         let _ = disable_raw_mode();
-        let _ = execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture);
+        let _ = execute!(
+            io::stdout(),
+            LeaveAlternateScreen,
+            DisableMouseCapture,
+            DisableBracketedPaste,
+            DisableFocusChange
+        );
         original_hook(panic_info);
     }));
 
     enable_raw_mode()?;
     let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen)?;
+    execute!(
+        stdout,
+        EnterAlternateScreen,
+        EnableBracketedPaste,
+        EnableFocusChange
+    )?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
     terminal.clear()?;
 
-    let mut app = App::new();
+    app.emulator = cli.emulator;
+    app.config = config;
+    app.watchdog = watchdog;
+    app.profile_watchdogs = profile_watchdogs;
+    app.grid_preview = cli.grid_preview;
+    app.keyboard_enhancement_supported =
+        crossterm::terminal::supports_keyboard_enhancement().unwrap_or(false);
     set_mouse_capture(&mut terminal, app.mouse_capture_enabled)?;
     let result = run(&mut terminal, &mut app);
 
+    if app.host_keyboard_flags != 0 {
+        execute!(terminal.backend_mut(), PopKeyboardEnhancementFlags)?;
+    }
+    if app.host_cursor_style != 0 {
+        execute!(terminal.backend_mut(), SetCursorStyle::DefaultUserShape)?;
+    }
+
     disable_raw_mode()?;
     execute!(
         terminal.backend_mut(),
         LeaveAlternateScreen,
-        DisableMouseCapture
+        DisableMouseCapture,
+        DisableBracketedPaste,
+        DisableFocusChange
     )?;
     terminal.show_cursor()?;
 
@@ -374,110 +1074,294 @@ fn set_mouse_capture<B: Backend + io::Write>(
     Ok(())
 }
 
+/// Mirrors the zoomed pane's kitty keyboard flags onto the host terminal so the events we
+/// receive carry enough detail to re-encode them for the child.
+fn sync_keyboard_enhancement<B: Backend + io::Write>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+) -> Result<()> {
+    if !app.keyboard_enhancement_supported {
+        return Ok(());
+    }
+    let wanted = if app.zoomed {
+        app.panes
+            .get(app.selected)
+            .map(|slot| slot.pane.kitty_flags())
+            .unwrap_or(0)
+    } else {
+        0
+    };
+    // Only the four flags crossterm knows about can be requested from the host.
+    let wanted = KeyboardEnhancementFlags::from_bits_truncate(wanted).bits();
+    if wanted == app.host_keyboard_flags {
+        return Ok(());
+    }
+    if app.host_keyboard_flags != 0 {
+        execute!(terminal.backend_mut(), PopKeyboardEnhancementFlags)?;
+    }
+    if wanted != 0 {
+        execute!(
+            terminal.backend_mut(),
+            PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::from_bits_truncate(wanted))
+        )?;
+    }
+    app.host_keyboard_flags = wanted;
+    Ok(())
+}
+
+/// Rewrites hyperlinked cells with their OSC 8 wrapping after a frame, leaving the cursor
+/// where ratatui put it.
+fn write_hyperlinks<B: Backend + io::Write>(
+    terminal: &mut Terminal<B>,
+    links: &[(u16, u16, ratatui::buffer::Cell)],
+) -> Result<()> {
+    if links.is_empty() {
+        return Ok(());
+    }
+    let backend = terminal.backend_mut();
+    queue!(backend, SavePosition)?;
+    backend.draw(links.iter().map(|(x, y, cell)| (*x, *y, cell)))?;
+    queue!(backend, RestorePosition)?;
+    Backend::flush(backend)?;
+    Ok(())
+}
+
+/// Shows the zoomed pane's DECSCUSR cursor shape on the host, and the host's own default
+/// otherwise.
+fn sync_cursor_style<B: Backend + io::Write>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+) -> Result<()> {
+    let wanted = if app.zoomed {
+        app.panes
+            .get(app.selected)
+            .map(|slot| slot.pane.cursor_style())
+            .unwrap_or(0)
+    } else {
+        0
+    };
+    if wanted == app.host_cursor_style {
+        return Ok(());
+    }
+    let style = match wanted {
+        1 => SetCursorStyle::BlinkingBlock,
+        2 => SetCursorStyle::SteadyBlock,
+        3 => SetCursorStyle::BlinkingUnderScore,
+        4 => SetCursorStyle::SteadyUnderScore,
+        5 => SetCursorStyle::BlinkingBar,
+        6 => SetCursorStyle::SteadyBar,
+        _ => SetCursorStyle::DefaultUserShape,
+    };
+    execute!(terminal.backend_mut(), style)?;
+    app.host_cursor_style = wanted;
+    Ok(())
+}
+
 fn run<B: Backend + io::Write>(terminal: &mut Terminal<B>, app: &mut App) -> Result<()> {
     let ts = terminal.size()?;
     let mut last_size = Rect::new(0, 0, ts.width, ts.height);
+    spawn_input_thread(app.events_tx.clone());
 
+    let background_frame = Duration::from_millis(BACKGROUND_FRAME_MS);
+    let mut dirty = true;
+    let mut background_dirty = false;
+    let mut last_draw = Instant::now();
+    let mut drawn_blink = app.blink_on();
     loop {
-        app.read_pty_output();
-        terminal.draw(|f| ui::draw(f, app))?;
-
-        if event::poll(Duration::from_millis(UI_POLL_MS))? {
-            match event::read()? {
-                Event::Resize(w, h) => {
-                    let size = Rect::new(0, 0, w, h);
-                    last_size = size;
-                    if app.zoomed {
-                        if let Some(pane) = app.panes.get_mut(app.selected) {
-                            let (rows, cols) = zoom_inner_size(h, w);
-                            pane.resize(rows, cols);
-                        }
-                    } else {
-                        app.resize_all_to_grid(h, w);
+        let activity = app.read_pty_output();
+        for notification in app.notifications.drain(..) {
+            notify::dispatch(&app.config.notify, &notification, terminal.backend_mut())?;
+        }
+        dirty |= activity.selected || activity.states;
+        // Hidden behind the zoomed pane, background output doesn't need a redraw at all.
+        background_dirty |= activity.background && !app.zoomed;
+        app.sync_focus();
+        sync_keyboard_enhancement(terminal, app)?;
+        sync_cursor_style(terminal, app)?;
+        let blink_on = app.blink_on();
+        let background_due = background_dirty && last_draw.elapsed() >= background_frame;
+        if dirty || background_due || (blink_on != drawn_blink && !app.attention_queue().is_empty())
+        {
+            let frame = terminal.draw(|f| ui::draw(f, app))?;
+            let links = ui::hyperlink_cells(app, frame.buffer);
+            write_hyperlinks(terminal, &links)?;
+            dirty = false;
+            background_dirty = false;
+            last_draw = Instant::now();
+            drawn_blink = blink_on;
+        }
+
+        // Sleep until PTY output, input, or the next timer (blink, input retry, output
+        // backlog or a throttled background redraw) is due.
+        let mut wakeup = app.next_wakeup();
+        if activity.backlog {
+            wakeup = earliest(wakeup, Duration::from_millis(BACKLOG_RETRY_MS));
+        }
+        if background_dirty {
+            wakeup = earliest(wakeup, background_frame.saturating_sub(last_draw.elapsed()));
+        }
+        let first = match wakeup {
+            Some(timeout) => match app.events_rx.recv_timeout(timeout) {
+                Ok(event) => Some(event),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            },
+            None => match app.events_rx.recv() {
+                Ok(event) => Some(event),
+                Err(_) => return Ok(()),
+            },
+        };
+        let pending: Vec<AppEvent> = first.into_iter().chain(app.events_rx.try_iter()).collect();
+        for event in pending {
+            match event {
+                AppEvent::Terminal(event) => {
+                    dirty = true;
+                    if !handle_event(terminal, app, event, &mut last_size)? {
+                        return Ok(());
                     }
                 }
-                Event::Key(key) if key.kind == KeyEventKind::Press => {
-                    let size = last_size;
-                    if key.code == KeyCode::F(2) {
-                        app.mouse_capture_enabled = !app.mouse_capture_enabled;
-                        set_mouse_capture(terminal, app.mouse_capture_enabled)?;
-                        continue;
+                AppEvent::Control(request) => {
+                    dirty = true;
+                    app.apply_report(request);
+                }
+                AppEvent::Query(query) => {
+                    let _ = query.reply.send(app.pane_statuses(query.pane));
+                }
+                AppEvent::PtyOutput | AppEvent::ChildExited => {}
+            }
+        }
+    }
+}
+
+fn earliest(current: Option<Duration>, candidate: Duration) -> Option<Duration> {
+    Some(current.map_or(candidate, |current| current.min(candidate)))
+}
+
+fn spawn_input_thread(tx: Sender<AppEvent>) {
+    thread::spawn(move || {
+        while let Ok(event) = event::read() {
+            if tx.send(AppEvent::Terminal(event)).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Handles one terminal event. Returns `Ok(false)` when the user asked to quit.
+fn handle_event<B: Backend + io::Write>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    event: Event,
+    last_size: &mut Rect,
+) -> Result<bool> {
+    match event {
+        Event::Resize(w, h) => {
+            let size = Rect::new(0, 0, w, h);
+            *last_size = size;
+            if app.zoomed {
+                if let Some(slot) = app.panes.get_mut(app.selected) {
+                    let (rows, cols) = zoom_inner_size(h, w);
+                    slot.pane.resize(rows, cols);
+                }
+            } else {
+                app.resize_all_to_grid(h, w);
+            }
+        }
+        // Repeats and releases only arrive with kitty flags enabled on the host.
+        Event::Key(key)
+            if key.kind != KeyEventKind::Press && app.zoomed && key.code != KeyCode::F(2) =>
+        {
+            app.send_key(&key);
+        }
+        Event::Key(key) if key.kind == KeyEventKind::Press => {
+            let size = *last_size;
+            if key.code == KeyCode::F(2) {
+                app.mouse_capture_enabled = !app.mouse_capture_enabled;
+                set_mouse_capture(terminal, app.mouse_capture_enabled)?;
+                return Ok(true);
+            }
+            if app.zoomed {
+                if key.code == KeyCode::Char(' ') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                    app.zoomed = false;
+                    app.scroll_offset = 0;
+                    app.resize_all_to_grid(size.height, size.width);
+                } else {
+                    app.send_key(&key);
+                }
+            } else {
+                match key.code {
+                    KeyCode::Char('q') => return Ok(false),
+                    KeyCode::Char('n') if app.panes.len() < 9 => {
+                        let _ = app.spawn_shell(24, 80);
+                        app.resize_all_to_grid(size.height, size.width);
+                    }
+                    KeyCode::Char('x') => {
+                        app.close_selected_pane();
+                    }
+                    KeyCode::Char('r') => {
+                        app.restart_selected_pane(size.height, size.width);
+                    }
+                    KeyCode::Char(']') => {
+                        app.focus_next_attention();
                     }
-                    if app.zoomed {
-                        if key.code == KeyCode::Char(' ')
-                            && key.modifiers.contains(KeyModifiers::CONTROL)
-                        {
-                            app.zoomed = false;
+                    KeyCode::Char('[') => {
+                        app.focus_prev_attention();
+                    }
+                    KeyCode::Char('a') => {
+                        app.acknowledge_selected_attention();
+                    }
+                    KeyCode::Char('b') => {
+                        app.toggle_selected_bell();
+                    }
+                    KeyCode::Char(c) if ('1'..='9').contains(&c) => {
+                        let idx = (c as u8 - b'1') as usize;
+                        if idx < app.panes.len() {
+                            app.selected = idx;
+                            app.zoomed = true;
                             app.scroll_offset = 0;
-                            app.resize_all_to_grid(size.height, size.width);
-                        } else {
-                            app.send_key(key.code, key.modifiers);
-                        }
-                    } else {
-                        match key.code {
-                            KeyCode::Char('q') => return Ok(()),
-                            KeyCode::Char('n') if app.panes.len() < 9 => {
-                                let _ = app.spawn_shell(24, 80);
-                                app.resize_all_to_grid(size.height, size.width);
-                            }
-                            KeyCode::Char('x') => {
-                                app.close_selected_pane();
-                            }
-                            KeyCode::Char('r') => {
-                                app.restart_selected_pane(size.height, size.width);
+                            if let Some(slot) = app.panes.get_mut(app.selected) {
+                                let (rows, cols) = zoom_inner_size(size.height, size.width);
+                                slot.pane.resize(rows, cols);
                             }
-                            KeyCode::Char(']') => {
-                                app.focus_next_attention();
-                            }
-                            KeyCode::Char('[') => {
-                                app.focus_prev_attention();
-                            }
-                            KeyCode::Char('a') => {
-                                app.acknowledge_selected_attention();
-                            }
-                            KeyCode::Char(c) if ('1'..='9').contains(&c) => {
-                                let idx = (c as u8 - b'1') as usize;
-                                if idx < app.panes.len() {
-                                    app.selected = idx;
-                                    app.zoomed = true;
-                                    app.scroll_offset = 0;
-                                    if let Some(pane) = app.panes.get_mut(app.selected) {
-                                        let (rows, cols) = zoom_inner_size(size.height, size.width);
-                                        pane.resize(rows, cols);
-                                    }
-                                }
-                            }
-                            KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right => {
-                                app.navigate(key.code);
-                            }
-                            KeyCode::Enter => {
-                                if !app.panes.is_empty() {
-                                    app.zoomed = true;
-                                    app.scroll_offset = 0;
-                                    if let Some(pane) = app.panes.get_mut(app.selected) {
-                                        let (rows, cols) = zoom_inner_size(size.height, size.width);
-                                        pane.resize(rows, cols);
-                                    }
-                                }
-                            }
-                            _ => {}
                         }
                     }
+                    KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right => {
+                        app.navigate(key.code);
+                    }
+                    KeyCode::Enter if !app.panes.is_empty() => {
+                        app.zoomed = true;
+                        app.scroll_offset = 0;
+                        if let Some(slot) = app.panes.get_mut(app.selected) {
+                            let (rows, cols) = zoom_inner_size(size.height, size.width);
+                            slot.pane.resize(rows, cols);
+                        }
+                    }
+                    _ => {}
                 }
-                Event::Mouse(mouse) if app.zoomed && app.mouse_capture_enabled => {
-                    app.handle_mouse(mouse, last_size);
-                }
-                _ => {}
             }
         }
+        Event::FocusGained => {
+            app.host_focused = true;
+        }
+        Event::FocusLost => {
+            app.host_focused = false;
+        }
+        Event::Paste(text) => {
+            app.paste(&text);
+        }
+        Event::Mouse(mouse) if app.zoomed && app.mouse_capture_enabled => {
+            app.handle_mouse(mouse, *last_size);
+        }
+        _ => {}
     }
+    Ok(true)
 }
 
 impl App {
     fn handle_mouse(&mut self, mouse: MouseEvent, term_area: Rect) {
-        if let Some(pane) = self.panes.get_mut(self.selected) {
-            let mode = pane.mouse_protocol_mode();
-            if mode == vt100::MouseProtocolMode::None {
+        if let Some(pane) = self.panes.get_mut(self.selected).map(|slot| &mut slot.pane) {
+            let mode = pane.mouse_mode();
+            if mode == MouseMode::None {
                 return;
             }
 
@@ -509,11 +1393,7 @@ impl App {
                 }
                 MouseEventKind::Up(_) => Some((3 | modifier_bits, true)),
                 MouseEventKind::Drag(button) => {
-                    if matches!(
-                        mode,
-                        vt100::MouseProtocolMode::ButtonMotion
-                            | vt100::MouseProtocolMode::AnyMotion
-                    ) {
+                    if matches!(mode, MouseMode::ButtonMotion | MouseMode::AnyMotion) {
                         let button_code = match button {
                             crossterm::event::MouseButton::Left => 0,
                             crossterm::event::MouseButton::Middle => 1,
@@ -525,7 +1405,7 @@ impl App {
                     }
                 }
                 MouseEventKind::Moved => {
-                    if mode == vt100::MouseProtocolMode::AnyMotion {
+                    if mode == MouseMode::AnyMotion {
                         Some((35 | modifier_bits, false))
                     } else {
                         None
@@ -538,9 +1418,7 @@ impl App {
             };
 
             if let Some((cb, release)) = event {
-                if let Some(bytes) =
-                    encode_xterm_mouse(pane.mouse_protocol_encoding(), cb, x, y, release)
-                {
+                if let Some(bytes) = encode_xterm_mouse(pane.mouse_encoding(), cb, x, y, release) {
                     let _ = pane.write_bytes(&bytes);
                 }
             }
@@ -0,0 +1,116 @@
+//! Tells the user about panes that need them through the host terminal's bell or a
+//! configured command, so they hear about it while looking at something else.
+
+use crate::config::NotifyConfig;
+use crate::state::{Finished, PaneState};
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+pub struct Notification {
+    pub pane: usize,
+    pub state: PaneState,
+    pub message: String,
+}
+
+/// `42s`, `12m` or `1h05m`.
+pub fn format_wait(waited: Duration) -> String {
+    let secs = waited.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        _ => format!("{}h{:02}m", secs / 3600, secs / 60 % 60),
+    }
+}
+
+impl Notification {
+    /// `detail` is what the pane itself reported, if anything.
+    pub fn attention(pane: usize, state: PaneState, detail: Option<&str>) -> Self {
+        let mut message = format!("shell {} is {}", pane + 1, describe(state));
+        if let Some(detail) = detail {
+            message.push_str(": ");
+            message.push_str(detail);
+        }
+        Self {
+            pane,
+            state,
+            message,
+        }
+    }
+
+    pub fn bell(pane: usize, state: PaneState) -> Self {
+        Self {
+            pane,
+            state,
+            message: format!("shell {} rang the bell", pane + 1),
+        }
+    }
+
+    pub fn finished(pane: usize, done: Finished) -> Self {
+        let outcome = match done.exit {
+            Some(code) if code != 0 => format!("failed (exit {})", code),
+            _ => "finished".to_string(),
+        };
+        Self {
+            pane,
+            state: PaneState::Idle,
+            message: format!(
+                "shell {} {} after {}",
+                pane + 1,
+                outcome,
+                format_wait(done.took)
+            ),
+        }
+    }
+
+    pub fn escalation(pane: usize, state: PaneState, waited: Duration) -> Self {
+        Self {
+            pane,
+            state,
+            message: format!(
+                "shell {} has been {} for {}",
+                pane + 1,
+                describe(state),
+                format_wait(waited)
+            ),
+        }
+    }
+}
+
+fn describe(state: PaneState) -> &'static str {
+    match state {
+        PaneState::WaitingForApproval => "waiting for approval",
+        PaneState::WaitingForInput => "waiting for input",
+        PaneState::Stalled => "stalled",
+        _ => state.label(),
+    }
+}
+
+pub fn dispatch(
+    config: &NotifyConfig,
+    notification: &Notification,
+    host: &mut impl Write,
+) -> io::Result<()> {
+    if config.bell {
+        host.write_all(b"\x07")?;
+        host.flush()?;
+    }
+    if let Some(command) = &config.command {
+        let child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .env("TERMOIL_PANE", (notification.pane + 1).to_string())
+            .env("TERMOIL_STATE", notification.state.label())
+            .env("TERMOIL_MESSAGE", &notification.message)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        // A failing notifier shouldn't take termoil down; reap it off the main loop.
+        if let Ok(mut child) = child {
+            thread::spawn(move || child.wait());
+        }
+    }
+    Ok(())
+}
@@ -1,27 +1,228 @@
+use crate::control::ReportedState;
+use crate::emulator::{self, Cell, EmulatorKind, MouseEncoding, MouseMode, TerminalEmulator};
+use crate::sequences::{KittyKeyboard, Progress, Query, Sequence, SequenceScanner, ShellMark};
+use crate::state::Finished;
+use crate::ui;
+use crate::watchdog::{self, Reach, WatchContext};
+use crate::AppEvent;
 use anyhow::Result;
-use nix::fcntl::{fcntl, FcntlArg, OFlag};
+use nix::fcntl::{fcntl, FcntlArg, FdFlag, OFlag};
+use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
 use nix::pty::openpty;
+use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
 use nix::sys::termios::{self, SetArg};
-use nix::unistd::{close, dup2, execvp, fork, read, setsid, write as nix_write, ForkResult, Pid};
+use nix::unistd::{
+    close, dup2, execvp, fork, pipe, pipe2, read, setsid, write as nix_write, ForkResult, Pid,
+};
+use std::collections::VecDeque;
 use std::ffi::CString;
-use std::os::fd::{AsRawFd, OwnedFd};
-use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::os::fd::{AsFd, AsRawFd, IntoRawFd, OwnedFd};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
 use std::thread;
+use std::time::{Duration, Instant};
 
 const PTY_READ_BUF_SIZE: usize = 16384;
-const PTY_IDLE_SLEEP_MS: u64 = 5;
+/// Chunks buffered between a reader thread and the UI. When full the reader blocks, the
+/// kernel PTY buffer fills, and the child is throttled instead of termoil's memory growing.
+const PTY_CHANNEL_CAPACITY: usize = 64;
+/// Upper bound on input queued for a child that isn't draining its PTY.
+const PTY_MAX_PENDING_INPUT: usize = 4 * 1024 * 1024;
+/// Kitty keyboard flag entries kept per pane; the oldest are dropped beyond this.
+const KITTY_STACK_LIMIT: usize = 16;
+/// How long a synchronized update (mode 2026) may hold back output before it's shown anyway,
+/// and how much output it may hold.
+const SYNC_UPDATE_TIMEOUT: Duration = Duration::from_millis(150);
+const SYNC_UPDATE_LIMIT: usize = 2 * 1024 * 1024;
+const BRACKETED_PASTE_START: &[u8] = b"\x1b[200~";
+const BRACKETED_PASTE_END: &[u8] = b"\x1b[201~";
 
 pub struct Pane {
-    parser: vt100::Parser,
+    emulator: Box<dyn TerminalEmulator>,
+    rows: u16,
+    cols: u16,
     master: OwnedFd,
+    /// Closed on drop, which tells the reader thread to stop.
+    _reader_stop: OwnedFd,
     rx: Receiver<Vec<u8>>,
     pub child_pid: Pid,
-    dsr_tail: Vec<u8>,
+    /// Exit code once the child has been reaped; 128 + signal if it was killed.
+    exit_code: Option<i32>,
     output_generation: u64,
+    last_output: Instant,
+    pending_input: VecDeque<u8>,
+    scanner: SequenceScanner,
+    /// Kitty keyboard flags for the main and the alternate screen.
+    kitty: [KittyFlags; 2],
+    /// Whether the child switched to the alternate screen, tracked with the output rather
+    /// than read from the emulator, which may not have caught up yet.
+    alternate_screen: bool,
+    focus_reporting: bool,
+    focused: bool,
+    cursor_style: u8,
+    /// Start of the synchronized update in progress, whose output is held in `sync_held`
+    /// (with the queries it contains) until it ends.
+    sync_since: Option<Instant>,
+    sync_held: Vec<u8>,
+    sync_queries: Vec<Query>,
+    /// States the program reported by printing, waiting for the app to apply them.
+    reports: Vec<(ReportedState, Option<String>)>,
+    progress: Option<Progress>,
+    /// Where the shell is, once it has sent OSC 133 marks.
+    shell_phase: Option<ShellPhase>,
+    /// Exit code of the last command, from the shell's `OSC 133 ; D` mark.
+    last_command_exit: Option<i32>,
+    /// When the running command started, from its `OSC 133 ; C` mark.
+    command_started: Option<Instant>,
+    /// Commands finished since the app last looked.
+    finished: Vec<Finished>,
+    /// The emulator's bell count when the app last looked.
+    bells_seen: usize,
+    hyperlinks_seen: bool,
+}
+
+/// Kitty keyboard flags in effect and the stack of earlier ones. The main and alternate
+/// screens each have their own, as the protocol asks.
+#[derive(Default)]
+struct KittyFlags {
+    flags: u8,
+    stack: Vec<u8>,
+}
+
+impl KittyFlags {
+    fn apply(&mut self, request: KittyKeyboard) {
+        match request {
+            KittyKeyboard::Push(flags) => {
+                if self.stack.len() >= KITTY_STACK_LIMIT {
+                    self.stack.remove(0);
+                }
+                self.stack.push(self.flags);
+                self.flags = flags;
+            }
+            KittyKeyboard::Pop(count) => {
+                for _ in 0..count {
+                    self.flags = self.stack.pop().unwrap_or(0);
+                }
+            }
+            KittyKeyboard::Set(flags, mode) => match mode {
+                2 => self.flags |= flags,
+                3 => self.flags &= !flags,
+                _ => self.flags = flags,
+            },
+        }
+    }
+}
+
+/// Where a shell with OSC 133 integration is between prompts and commands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ShellPhase {
+    Prompt,
+    Running,
+}
+
+/// The reply to an `OSC 10 ; ?` or `OSC 11 ; ?` color query, terminated the way the query
+/// was.
+fn color_reply(code: u8, bell: bool) -> String {
+    let (r, g, b) = if code == 10 { ui::TEXT_RGB } else { ui::BG_RGB };
+    let terminator = if bell { "\x07" } else { "\x1b\\" };
+    // 8-bit channels scaled to the 16-bit form xterm reports.
+    format!(
+        "\x1b]{};rgb:{:04x}/{:04x}/{:04x}{}",
+        code,
+        r as u16 * 0x101,
+        g as u16 * 0x101,
+        b as u16 * 0x101,
+        terminator
+    )
+}
+
+/// Version encoded as a single number for DA2, e.g. 0.1.1 -> 101.
+fn version_number() -> u32 {
+    env!("CARGO_PKG_VERSION")
+        .split('.')
+        .take(3)
+        .fold(0, |acc, part| acc * 100 + part.parse::<u32>().unwrap_or(0))
+}
+
+#[cfg(target_os = "linux")]
+fn process_name(pid: Pid) -> Option<String> {
+    let comm = std::fs::read_to_string(format!("/proc/{}/comm", pid)).ok()?;
+    Some(comm.trim_end().to_string())
+}
+
+#[cfg(target_os = "macos")]
+fn process_name(pid: Pid) -> Option<String> {
+    let mut buf = [0u8; 256];
+    let len = unsafe { libc::proc_name(pid.as_raw(), buf.as_mut_ptr().cast(), buf.len() as u32) };
+    if len <= 0 {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&buf[..len as usize]).into_owned())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn process_name(_pid: Pid) -> Option<String> {
+    None
+}
+
+/// Write end of the pipe the SIGCHLD handler pokes.
+static CHILD_EXIT_PIPE: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn on_sigchld(_: libc::c_int) {
+    let fd = CHILD_EXIT_PIPE.load(Ordering::Relaxed);
+    if fd >= 0 {
+        // Only async-signal-safe calls here; a full pipe already has a wake pending.
+        unsafe { libc::write(fd, [0u8].as_ptr().cast(), 1) };
+    }
+}
+
+/// Wakes the main loop with `AppEvent::ChildExited` whenever a child exits, so a pane's
+/// exit is picked up even if its PTY never reports EOF (a background job can hold it open)
+/// or the last output arrived before the child could be reaped.
+pub fn watch_child_exits(wake: Sender<AppEvent>) -> Result<()> {
+    let (read_end, write_end) = pipe()?;
+    for fd in [&read_end, &write_end] {
+        fcntl(fd.as_raw_fd(), FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC))?;
+    }
+    fcntl(write_end.as_raw_fd(), FcntlArg::F_SETFL(OFlag::O_NONBLOCK))?;
+    CHILD_EXIT_PIPE.store(write_end.into_raw_fd(), Ordering::Relaxed);
+    let action = SigAction::new(
+        SigHandler::Handler(on_sigchld),
+        SaFlags::SA_RESTART | SaFlags::SA_NOCLDSTOP,
+        SigSet::empty(),
+    );
+    unsafe { sigaction(Signal::SIGCHLD, &action) }?;
+    thread::spawn(move || {
+        let mut buf = [0u8; 64];
+        loop {
+            match read(read_end.as_raw_fd(), &mut buf) {
+                Ok(0) => break,
+                Ok(_) => {
+                    if wake.send(AppEvent::ChildExited).is_err() {
+                        break;
+                    }
+                }
+                Err(nix::errno::Errno::EINTR) => {}
+                Err(_) => break,
+            }
+        }
+    });
+    Ok(())
 }
 
 impl Pane {
-    pub fn spawn_shell(rows: u16, cols: u16) -> Result<Self> {
+    pub fn spawn_shell(
+        rows: u16,
+        cols: u16,
+        emulator_kind: EmulatorKind,
+        env: &[(&str, String)],
+        wake: Sender<AppEvent>,
+    ) -> Result<Self> {
+        // Built before forking, so the child only has to hand them to putenv.
+        let env: Vec<CString> = env
+            .iter()
+            .filter_map(|(key, value)| CString::new(format!("{key}={value}")).ok())
+            .collect();
         let pty = openpty(None, None)?;
         let master_raw = pty.master.as_raw_fd();
         let slave_raw = pty.slave.as_raw_fd();
@@ -52,6 +253,9 @@ impl Pane {
                 let shell_c = CString::new(shell.as_str()).unwrap();
                 let term = CString::new("TERM=xterm-256color").unwrap();
                 unsafe { libc::putenv(term.into_raw()) };
+                for var in env {
+                    unsafe { libc::putenv(var.into_raw()) };
+                }
 
                 let _ = execvp(&shell_c, &[&shell_c]);
                 std::process::exit(1);
@@ -69,130 +273,390 @@ impl Pane {
                 new_flags.insert(OFlag::O_NONBLOCK);
                 fcntl(master_raw, FcntlArg::F_SETFL(new_flags))?;
 
-                let (tx, rx) = mpsc::channel();
+                let (tx, rx) = mpsc::sync_channel(PTY_CHANNEL_CAPACITY);
+                // The reader owns its own descriptor, so closing the pane can't leave it reading
+                // whatever reuses the number; the pane drops `reader_stop` to end it.
+                let reader_fd = pty.master.try_clone()?;
+                let (stop_read, reader_stop) = pipe2(OFlag::O_CLOEXEC)?;
 
+                // The master is non-blocking for writes, so the reader blocks in poll() instead.
                 thread::spawn(move || {
                     let mut buf = [0u8; PTY_READ_BUF_SIZE];
                     loop {
-                        match read(master_raw, &mut buf) {
+                        let mut fds = [
+                            PollFd::new(reader_fd.as_fd(), PollFlags::POLLIN),
+                            PollFd::new(stop_read.as_fd(), PollFlags::POLLIN),
+                        ];
+                        match poll(&mut fds, PollTimeout::NONE) {
+                            Ok(_) | Err(nix::errno::Errno::EINTR) => {}
+                            Err(_) => break,
+                        }
+                        if fds[1].any().unwrap_or(true) {
+                            break;
+                        }
+                        match read(reader_fd.as_raw_fd(), &mut buf) {
                             Ok(0) => break,
                             Ok(n) => {
                                 if tx.send(buf[..n].to_vec()).is_err() {
                                     break;
                                 }
+                                let _ = wake.send(AppEvent::PtyOutput);
                             }
-                            Err(nix::errno::Errno::EAGAIN) => {
-                                thread::sleep(std::time::Duration::from_millis(PTY_IDLE_SLEEP_MS));
-                            }
+                            Err(nix::errno::Errno::EAGAIN | nix::errno::Errno::EINTR) => {}
                             Err(_) => break,
                         }
                     }
+                    let _ = wake.send(AppEvent::PtyOutput);
                 });
 
                 Ok(Self {
-                    parser: vt100::Parser::new(rows, cols, 1000),
+                    emulator: emulator::new_emulator(emulator_kind, rows, cols, 1000),
+                    rows,
+                    cols,
                     master: pty.master,
+                    _reader_stop: reader_stop,
                     rx,
                     child_pid: child,
-                    dsr_tail: Vec::new(),
+                    exit_code: None,
                     output_generation: 0,
+                    last_output: Instant::now(),
+                    pending_input: VecDeque::new(),
+                    scanner: SequenceScanner::new(),
+                    kitty: Default::default(),
+                    alternate_screen: false,
+                    focus_reporting: false,
+                    focused: false,
+                    cursor_style: 0,
+                    sync_since: None,
+                    sync_held: Vec::new(),
+                    sync_queries: Vec::new(),
+                    reports: Vec::new(),
+                    progress: None,
+                    shell_phase: None,
+                    last_command_exit: None,
+                    command_started: None,
+                    finished: Vec::new(),
+                    bells_seen: 0,
+                    hyperlinks_seen: false,
                 })
             }
         }
     }
 
-    pub fn read_available(&mut self) {
-        let mut dsr_count = 0u32;
+    /// Parses pending output, stopping once roughly `budget` bytes have been processed.
+    /// Returns `true` if the budget ran out and more output may be waiting.
+    pub fn read_available(&mut self, budget: usize) -> bool {
+        self.flush_input();
+
+        let mut processed = 0;
         loop {
+            if processed >= budget {
+                break;
+            }
             match self.rx.try_recv() {
                 Ok(data) => {
-                    self.output_generation = self.output_generation.wrapping_add(1);
-
-                    // Check for DSR across chunk boundary
-                    let mut check = std::mem::take(&mut self.dsr_tail);
-                    check.extend_from_slice(&data);
-                    for window in check.windows(4) {
-                        if window == b"\x1b[6n" {
-                            dsr_count += 1;
+                    processed += data.len();
+
+                    // The scanner keeps parser state, so queries split across chunks still match.
+                    for sequence in self.scanner.scan(&data) {
+                        match sequence {
+                            Sequence::Kitty(request) => self.kitty_mut().apply(request),
+                            Sequence::Query(query) => self.sync_queries.push(query),
+                            Sequence::PrivateMode(mode, enabled) => {
+                                self.set_private_mode(mode, enabled)
+                            }
+                            Sequence::CursorStyle(style) => self.cursor_style = style,
+                            Sequence::Report(state, message) => {
+                                self.reports.push((state, message));
+                            }
+                            Sequence::Progress(progress) => self.progress = progress,
+                            Sequence::ShellMark(mark) => self.apply_shell_mark(mark),
+                            Sequence::Hyperlink => self.hyperlinks_seen = true,
+                            Sequence::Reset => self.reset(),
                         }
                     }
-                    let tail_start = check.len().saturating_sub(3);
-                    self.dsr_tail = check[tail_start..].to_vec();
 
-                    self.parser.process(&data);
+                    // A frame drawn inside a synchronized update is only shown once it's done,
+                    // so the screen never holds half of it.
+                    self.sync_held.extend_from_slice(&data);
+                    if self.sync_since.is_none() || self.sync_held.len() >= SYNC_UPDATE_LIMIT {
+                        self.end_sync_update();
+                    }
                 }
                 Err(TryRecvError::Empty) => break,
-                Err(TryRecvError::Disconnected) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.end_sync_update();
+                    break;
+                }
             }
         }
-        for _ in 0..dsr_count {
-            let (row, col) = self.parser.screen().cursor_position();
-            let resp = format!("\x1b[{};{}R", row + 1, col + 1);
-            let _ = self.write_bytes(resp.as_bytes());
+        if self
+            .sync_since
+            .is_some_and(|since| since.elapsed() >= SYNC_UPDATE_TIMEOUT)
+        {
+            self.end_sync_update();
         }
 
         // Reap zombie child
-        use nix::sys::wait::{waitpid, WaitPidFlag};
-        let _ = waitpid(self.child_pid, Some(WaitPidFlag::WNOHANG));
+        use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+        if self.exit_code.is_none() {
+            match waitpid(self.child_pid, Some(WaitPidFlag::WNOHANG)) {
+                Ok(WaitStatus::Exited(_, code)) => self.exit_code = Some(code),
+                Ok(WaitStatus::Signaled(_, signal, _)) => {
+                    self.exit_code = Some(128 + signal as i32)
+                }
+                _ => {}
+            }
+        }
+        processed >= budget
+    }
+
+    /// Feeds held output to the emulator and answers the queries in it.
+    fn end_sync_update(&mut self) {
+        self.sync_since = None;
+        if self.sync_held.is_empty() {
+            return;
+        }
+        self.output_generation = self.output_generation.wrapping_add(1);
+        self.last_output = Instant::now();
+        self.emulator.process(&self.sync_held);
+        self.sync_held.clear();
+        for query in std::mem::take(&mut self.sync_queries) {
+            let resp = self.answer_query(&query);
+            let _ = self.write_bytes(&resp);
+        }
+    }
+
+    /// When a synchronized update still holding output must be shown even if it hasn't
+    /// ended.
+    pub fn sync_deadline(&self) -> Option<Instant> {
+        self.sync_since.map(|since| since + SYNC_UPDATE_TIMEOUT)
+    }
+
+    fn kitty(&self) -> &KittyFlags {
+        &self.kitty[self.alternate_screen as usize]
+    }
+
+    fn kitty_mut(&mut self) -> &mut KittyFlags {
+        &mut self.kitty[self.alternate_screen as usize]
+    }
+
+    /// RIS (`ESC c`): the emulator resets itself, and the modes tracked here go back to
+    /// their defaults with it, so `reset` recovers a terminal a killed program left behind.
+    fn reset(&mut self) {
+        self.kitty = Default::default();
+        self.alternate_screen = false;
+        self.focus_reporting = false;
+        self.cursor_style = 0;
+        // The output held so far is shown, with the reset, once this chunk is processed.
+        self.sync_since = None;
+    }
+
+    fn set_private_mode(&mut self, mode: u16, enabled: bool) {
+        match mode {
+            47 | 1047 | 1049 => {
+                // Each entry to the alternate screen starts it with no flags pushed.
+                if enabled && !self.alternate_screen {
+                    self.kitty[1] = KittyFlags::default();
+                }
+                self.alternate_screen = enabled;
+            }
+            1004 => self.focus_reporting = enabled,
+            2026 if enabled => {
+                self.sync_since.get_or_insert_with(Instant::now);
+            }
+            2026 => self.sync_since = None,
+            _ => {}
+        }
+    }
+
+    /// Tells the child whether it has focus, if it asked for focus reports (mode 1004).
+    pub fn set_focused(&mut self, focused: bool) {
+        if self.focused == focused {
+            return;
+        }
+        self.focused = focused;
+        if self.focus_reporting {
+            let report: &[u8] = if focused { b"\x1b[I" } else { b"\x1b[O" };
+            let _ = self.write_bytes(report);
+        }
+    }
+
+    fn answer_query(&self, query: &Query) -> Vec<u8> {
+        let screen = &self.emulator;
+        let resp = match query {
+            Query::Status => "\x1b[0n".to_string(),
+            Query::CursorPosition => {
+                let (row, col) = screen.cursor_position();
+                format!("\x1b[{};{}R", row + 1, col + 1)
+            }
+            Query::ExtendedCursorPosition => {
+                let (row, col) = screen.cursor_position();
+                format!("\x1b[?{};{};1R", row + 1, col + 1)
+            }
+            // VT220 with ANSI color.
+            Query::PrimaryAttributes => "\x1b[?62;22c".to_string(),
+            Query::SecondaryAttributes => format!("\x1b[>1;{};0c", version_number()),
+            Query::Version => format!("\x1bP>|termoil({})\x1b\\", env!("CARGO_PKG_VERSION")),
+            Query::PrivateMode(mode) => {
+                let state = match self.private_mode(*mode) {
+                    Some(true) => 1,
+                    Some(false) => 2,
+                    None => 0,
+                };
+                format!("\x1b[?{};{}$y", mode, state)
+            }
+            Query::Color { code, bell } => color_reply(*code, *bell),
+            Query::KittyKeyboard => format!("\x1b[?{}u", self.kitty().flags),
+        };
+        resp.into_bytes()
+    }
+
+    fn private_mode(&self, mode: u16) -> Option<bool> {
+        let screen = &self.emulator;
+        let mouse = screen.mouse_mode();
+        Some(match mode {
+            1 => screen.application_cursor(),
+            25 => !screen.hide_cursor(),
+            47 | 1047 | 1049 => screen.alternate_screen(),
+            1000 => mouse == MouseMode::PressRelease,
+            1002 => mouse == MouseMode::ButtonMotion,
+            1003 => mouse == MouseMode::AnyMotion,
+            1004 => self.focus_reporting,
+            1006 => screen.mouse_encoding() == MouseEncoding::Sgr,
+            2004 => screen.bracketed_paste(),
+            2026 => self.sync_since.is_some(),
+            _ => return None,
+        })
+    }
+
+    /// DECSCUSR cursor style requested by the child (0 = the host's default).
+    pub fn cursor_style(&self) -> u8 {
+        self.cursor_style
+    }
+
+    /// Kitty keyboard protocol flags currently requested by the child (0 = legacy encoding).
+    pub fn kitty_flags(&self) -> u8 {
+        self.kitty().flags
     }
 
     pub fn screen_contents(&self) -> String {
-        self.parser.screen().contents()
+        self.emulator.contents()
     }
 
     pub fn scrollback_len(&self) -> usize {
-        self.parser.screen().scrollback()
+        self.emulator.scrollback()
     }
 
     pub fn contents_with_scrollback(&self) -> String {
-        self.parser.screen().contents()
+        self.emulator.contents()
     }
 
     pub fn cursor_position(&self) -> (u16, u16) {
-        self.parser.screen().cursor_position()
+        self.emulator.cursor_position()
     }
 
     pub fn hide_cursor(&self) -> bool {
-        self.parser.screen().hide_cursor()
+        self.emulator.hide_cursor()
+    }
+
+    pub fn application_cursor(&self) -> bool {
+        self.emulator.application_cursor()
+    }
+
+    pub fn mouse_mode(&self) -> MouseMode {
+        self.emulator.mouse_mode()
     }
 
-    pub fn mouse_protocol_mode(&self) -> vt100::MouseProtocolMode {
-        self.parser.screen().mouse_protocol_mode()
+    pub fn mouse_encoding(&self) -> MouseEncoding {
+        self.emulator.mouse_encoding()
     }
 
-    pub fn mouse_protocol_encoding(&self) -> vt100::MouseProtocolEncoding {
-        self.parser.screen().mouse_protocol_encoding()
+    pub fn visit_row(&self, row: u16, start: u16, end: u16, mut visit: impl FnMut(u16, &Cell)) {
+        self.emulator.visit_row(row, start, end, &mut visit);
     }
 
-    pub fn cell(&self, row: u16, col: u16) -> Option<&vt100::Cell> {
-        self.parser.screen().cell(row, col)
+    /// Whether the program has printed OSC 8 hyperlinks that the emulator kept, so there may
+    /// be some to re-emit.
+    pub fn has_hyperlinks(&self) -> bool {
+        self.hyperlinks_seen && self.emulator.keeps_hyperlinks()
     }
 
-    pub fn cursor_line(&self) -> String {
-        let screen = self.parser.screen();
-        let cursor_row = screen.cursor_position().0 as usize;
-        let contents = screen.contents();
-        contents.lines().nth(cursor_row).unwrap_or("").to_string()
+    /// What the watchdog sees of this pane's screen.
+    pub fn watch_context(&self, reach: Reach) -> WatchContext {
+        let mut context =
+            watchdog::watch_context(self.emulator.as_ref(), self.rows, self.cols, reach);
+        context.at_prompt = self.shell_phase.map(|phase| phase == ShellPhase::Prompt);
+        context
     }
 
-    pub fn lines_near_cursor(&self) -> String {
-        let screen = self.parser.screen();
-        let cursor_row = screen.cursor_position().0 as usize;
-        let contents = screen.contents();
-        let lines: Vec<&str> = contents.lines().collect();
-        if lines.is_empty() {
-            return String::new();
+    fn apply_shell_mark(&mut self, mark: ShellMark) {
+        match mark {
+            ShellMark::PromptStart | ShellMark::CommandStart => {
+                self.shell_phase = Some(ShellPhase::Prompt);
+            }
+            ShellMark::CommandExecuted => {
+                self.shell_phase = Some(ShellPhase::Running);
+                self.command_started = Some(Instant::now());
+            }
+            ShellMark::CommandFinished(code) => {
+                self.shell_phase = Some(ShellPhase::Prompt);
+                self.last_command_exit = code;
+                // A `D` without a `C` closes an empty command line.
+                if let Some(started) = self.command_started.take() {
+                    self.finished.push(Finished {
+                        exit: code,
+                        took: started.elapsed(),
+                    });
+                }
+            }
         }
+    }
+
+    /// Exit code of the last command the shell reported finishing.
+    pub fn last_command_exit(&self) -> Option<i32> {
+        self.last_command_exit
+    }
+
+    /// Whether the shell marks its prompts and commands, so their ends are known exactly.
+    pub fn has_shell_marks(&self) -> bool {
+        self.shell_phase.is_some()
+    }
+
+    /// Commands the shell reported finishing since the last call.
+    pub fn take_finished(&mut self) -> Vec<Finished> {
+        std::mem::take(&mut self.finished)
+    }
 
-        // `cursor_row` is based on terminal rows; `contents.lines()` omits some trailing empty rows.
-        // Clamp to the available slice bounds to avoid out-of-range panics.
-        let clamped_row = cursor_row.min(lines.len().saturating_sub(1));
-        let start = clamped_row.saturating_sub(5);
-        let end = clamped_row + 1;
-        lines[start..end].join("\n")
+    /// Whether the program rang the bell since the last call.
+    pub fn take_bell(&mut self) -> bool {
+        let count = self.emulator.bell_count();
+        let rang = count != self.bells_seen;
+        self.bells_seen = count;
+        rang
+    }
+
+    /// States reported through OSC sequences since the last call.
+    pub fn take_reports(&mut self) -> Vec<(ReportedState, Option<String>)> {
+        std::mem::take(&mut self.reports)
+    }
+
+    /// Progress last reported with `OSC 9 ; 4`.
+    pub fn progress(&self) -> Option<Progress> {
+        self.progress
+    }
+
+    /// `(rows, cols)` of the PTY.
+    pub fn size(&self) -> (u16, u16) {
+        (self.rows, self.cols)
     }
 
     pub fn resize(&mut self, rows: u16, cols: u16) {
+        if (rows, cols) == (self.rows, self.cols) {
+            return;
+        }
+        self.rows = rows;
+        self.cols = cols;
         let ws = libc::winsize {
             ws_row: rows,
             ws_col: cols,
@@ -200,14 +664,76 @@ impl Pane {
             ws_ypixel: 0,
         };
         unsafe { libc::ioctl(self.master.as_raw_fd(), libc::TIOCSWINSZ, &ws) };
-        self.parser.set_size(rows, cols);
+        self.emulator.resize(rows, cols);
     }
 
+    /// Queues `data` for the child and writes as much as the PTY accepts right now.
+    /// Whatever is left is retried on the next `read_available`.
     pub fn write_bytes(&mut self, data: &[u8]) -> Result<()> {
-        let _ = nix_write(&self.master, data);
+        if self.pending_input.len() + data.len() > PTY_MAX_PENDING_INPUT {
+            anyhow::bail!("pty input queue full");
+        }
+        self.pending_input.extend(data);
+        self.flush_input();
         Ok(())
     }
 
+    pub fn paste(&mut self, text: &str) -> Result<()> {
+        // Terminals send pasted line breaks as CR, like a typed Enter.
+        let text = text.replace("\r\n", "\r").replace('\n', "\r");
+        if !self.emulator.bracketed_paste() {
+            return self.write_bytes(text.as_bytes());
+        }
+        // Strip embedded end markers so pasted content can't break out of the bracket.
+        let text = text.replace("\x1b[201~", "");
+        let mut data = Vec::with_capacity(
+            BRACKETED_PASTE_START.len() + text.len() + BRACKETED_PASTE_END.len(),
+        );
+        data.extend_from_slice(BRACKETED_PASTE_START);
+        data.extend_from_slice(text.as_bytes());
+        data.extend_from_slice(BRACKETED_PASTE_END);
+        self.write_bytes(&data)
+    }
+
+    pub fn has_pending_input(&self) -> bool {
+        !self.pending_input.is_empty()
+    }
+
+    fn flush_input(&mut self) {
+        while !self.pending_input.is_empty() {
+            let (chunk, _) = self.pending_input.as_slices();
+            match nix_write(&self.master, chunk) {
+                Ok(0) => break,
+                Ok(n) => {
+                    self.pending_input.drain(..n);
+                }
+                Err(nix::errno::Errno::EINTR) => continue,
+                Err(nix::errno::Errno::EAGAIN) => break,
+                Err(_) => {
+                    // The child is gone; nothing will ever drain the queue.
+                    self.pending_input.clear();
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Time since output last reached the screen (or since spawning, before any).
+    pub fn since_output(&self) -> Duration {
+        self.last_output.elapsed()
+    }
+
+    /// Name of the process in the PTY's foreground process group, e.g. `claude` while an
+    /// agent runs in the shell.
+    pub fn foreground_command(&self) -> Option<String> {
+        let pgid = nix::unistd::tcgetpgrp(&self.master).ok()?;
+        process_name(pgid)
+    }
+
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
     pub fn output_generation(&self) -> u64 {
         self.output_generation
     }
@@ -218,3 +744,37 @@ impl Pane {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_reply_matches_the_query_terminator() {
+        assert_eq!(color_reply(11, true), "\x1b]11;rgb:1414/0f0f/1e1e\x07");
+        assert_eq!(color_reply(11, false), "\x1b]11;rgb:1414/0f0f/1e1e\x1b\\");
+        assert_eq!(color_reply(10, true), "\x1b]10;rgb:dcdc/dcdc/e6e6\x07");
+    }
+
+    #[test]
+    fn kitty_flags_push_pop_and_set() {
+        let mut kitty = KittyFlags::default();
+        kitty.apply(KittyKeyboard::Push(1));
+        kitty.apply(KittyKeyboard::Push(0b1011));
+        assert_eq!(kitty.flags, 0b1011);
+        kitty.apply(KittyKeyboard::Set(0b0010, 3));
+        assert_eq!(kitty.flags, 0b1001);
+        kitty.apply(KittyKeyboard::Set(0b0100, 2));
+        assert_eq!(kitty.flags, 0b1101);
+        kitty.apply(KittyKeyboard::Pop(1));
+        assert_eq!(kitty.flags, 1);
+        // Popping past the bottom leaves the legacy encoding.
+        kitty.apply(KittyKeyboard::Pop(5));
+        assert_eq!(kitty.flags, 0);
+
+        for flags in 0..=KITTY_STACK_LIMIT as u8 + 2 {
+            kitty.apply(KittyKeyboard::Push(flags));
+        }
+        assert_eq!(kitty.stack.len(), KITTY_STACK_LIMIT);
+    }
+}
@@ -0,0 +1,445 @@
+//! Scans child output for control sequences that vt100 doesn't track but termoil needs to
+//! act on. The scanner keeps its own parser state, so sequences split across reads are seen.
+
+use crate::control::ReportedState;
+use clap::ValueEnum;
+
+/// termoil's own OSC: `OSC 7717 ; state [; message] ST`, with a state `termoil notify`
+/// accepts.
+const TERMOIL_OSC: &[u8] = b"7717";
+
+/// A control sequence of interest found in the PTY output stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sequence {
+    Kitty(KittyKeyboard),
+    Query(Query),
+    /// `CSI ? mode h` / `CSI ? mode l`: DEC private mode set or reset.
+    PrivateMode(u16, bool),
+    /// `CSI Ps SP q` (DECSCUSR): cursor shape and blink, 0 meaning the user's default.
+    CursorStyle(u8),
+    /// A program reporting its state by printing: termoil's own OSC, or an `OSC 9 ; message`
+    /// or `OSC 777 ; notify ; title ; body` desktop notification, which asks for the user.
+    Report(ReportedState, Option<String>),
+    /// `OSC 9 ; 4 ; state ; percent` (ConEmu progress); `None` once the progress is removed.
+    Progress(Option<Progress>),
+    /// `OSC 133 ; mark`: shell integration marks around prompts and commands.
+    ShellMark(ShellMark),
+    /// `OSC 8 ; params ; uri` opening a hyperlink.
+    Hyperlink,
+    /// `ESC c` (RIS): full reset.
+    Reset,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShellMark {
+    /// `A`: the prompt is about to be drawn.
+    PromptStart,
+    /// `B`: the prompt is drawn and the user is typing a command.
+    CommandStart,
+    /// `C`: the command is running.
+    CommandExecuted,
+    /// `D [; exit]`: the command finished.
+    CommandFinished(Option<i32>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Progress {
+    Normal(u8),
+    Error(u8),
+    Indeterminate,
+    Paused(u8),
+}
+
+/// Requests for information the terminal is expected to answer on the PTY.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Query {
+    /// `CSI 5 n`: device status report.
+    Status,
+    /// `CSI 6 n`: cursor position report.
+    CursorPosition,
+    /// `CSI ? 6 n`: DEC extended cursor position report.
+    ExtendedCursorPosition,
+    /// `CSI c`: primary device attributes (DA1).
+    PrimaryAttributes,
+    /// `CSI > c`: secondary device attributes (DA2).
+    SecondaryAttributes,
+    /// `CSI > q`: terminal name and version (XTVERSION).
+    Version,
+    /// `CSI ? mode $ p`: DEC private mode state (DECRQM).
+    PrivateMode(u16),
+    /// `OSC 10 ; ?` / `OSC 11 ; ?`: default foreground or background color. `bell` records
+    /// whether the query was BEL-terminated so the reply can match.
+    Color { code: u8, bell: bool },
+    /// `CSI ? u`: current kitty keyboard flags.
+    KittyKeyboard,
+}
+
+/// Kitty progressive keyboard enhancement requests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KittyKeyboard {
+    /// `CSI > flags u`: push flags onto the stack.
+    Push(u8),
+    /// `CSI < n u`: pop `n` stack entries.
+    Pop(u16),
+    /// `CSI = flags ; mode u`: replace (1), set (2) or clear (3) the current flags.
+    Set(u8, u8),
+}
+
+#[derive(Default)]
+struct Collector {
+    found: Vec<Sequence>,
+}
+
+fn first_param(params: &vte::Params) -> Option<u16> {
+    params.iter().next().and_then(|p| p.first().copied())
+}
+
+impl vte::Perform for Collector {
+    fn esc_dispatch(&mut self, intermediates: &[u8], ignore: bool, byte: u8) {
+        if !ignore && intermediates.is_empty() && byte == b'c' {
+            self.found.push(Sequence::Reset);
+        }
+    }
+
+    fn csi_dispatch(
+        &mut self,
+        params: &vte::Params,
+        intermediates: &[u8],
+        ignore: bool,
+        action: char,
+    ) {
+        if ignore {
+            return;
+        }
+        match (intermediates, action) {
+            (b">", 'u') => {
+                let flags = first_param(params).unwrap_or(0);
+                self.found
+                    .push(Sequence::Kitty(KittyKeyboard::Push(flags as u8)));
+            }
+            (b"<", 'u') => {
+                let count = first_param(params).unwrap_or(1).max(1);
+                self.found.push(Sequence::Kitty(KittyKeyboard::Pop(count)));
+            }
+            (b"=", 'u') => {
+                let mut iter = params.iter();
+                let flags = iter.next().and_then(|p| p.first().copied()).unwrap_or(0);
+                let mode = iter.next().and_then(|p| p.first().copied()).unwrap_or(1);
+                self.found
+                    .push(Sequence::Kitty(KittyKeyboard::Set(flags as u8, mode as u8)));
+            }
+            (b"?", 'u') => self.found.push(Sequence::Query(Query::KittyKeyboard)),
+            (b"", 'n') => match first_param(params) {
+                Some(5) => self.found.push(Sequence::Query(Query::Status)),
+                Some(6) => self.found.push(Sequence::Query(Query::CursorPosition)),
+                _ => {}
+            },
+            (b"?", 'n') if first_param(params) == Some(6) => {
+                self.found
+                    .push(Sequence::Query(Query::ExtendedCursorPosition));
+            }
+            (b"", 'c') if first_param(params).unwrap_or(0) == 0 => {
+                self.found.push(Sequence::Query(Query::PrimaryAttributes));
+            }
+            (b">", 'c') if first_param(params).unwrap_or(0) == 0 => {
+                self.found.push(Sequence::Query(Query::SecondaryAttributes));
+            }
+            (b">", 'q') if first_param(params).unwrap_or(0) == 0 => {
+                self.found.push(Sequence::Query(Query::Version));
+            }
+            (b"?", 'h') | (b"?", 'l') => {
+                for param in params.iter() {
+                    if let Some(&mode) = param.first() {
+                        self.found.push(Sequence::PrivateMode(mode, action == 'h'));
+                    }
+                }
+            }
+            (b" ", 'q') => {
+                let style = first_param(params).unwrap_or(0);
+                if style <= 6 {
+                    self.found.push(Sequence::CursorStyle(style as u8));
+                }
+            }
+            (b"?$", 'p') => {
+                if let Some(mode) = first_param(params) {
+                    self.found.push(Sequence::Query(Query::PrivateMode(mode)));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn osc_dispatch(&mut self, params: &[&[u8]], bell_terminated: bool) {
+        // Messages may contain `;`, which vte splits on.
+        let text = |from: usize| {
+            let parts = params.get(from..).unwrap_or(&[]).join(&b';');
+            let text = String::from_utf8_lossy(&parts).into_owned();
+            (!text.is_empty()).then_some(text)
+        };
+        match params.first().copied() {
+            Some(TERMOIL_OSC) => {
+                let state = params.get(1).map(|state| String::from_utf8_lossy(state));
+                if let Some(Ok(state)) = state.map(|state| ReportedState::from_str(&state, true)) {
+                    self.found.push(Sequence::Report(state, text(2)));
+                }
+                return;
+            }
+            Some(b"9") => {
+                // `OSC 9 ; n ; ...` with a number is a ConEmu command rather than a message;
+                // of those, only 4 (progress) matters here.
+                let number = params
+                    .get(1)
+                    .and_then(|param| std::str::from_utf8(param).ok())
+                    .and_then(|param| param.parse::<u8>().ok());
+                match number {
+                    Some(4) => self.found.push(Sequence::Progress(progress(params))),
+                    Some(_) => {}
+                    None => self
+                        .found
+                        .push(Sequence::Report(ReportedState::Input, text(1))),
+                }
+                return;
+            }
+            Some(b"133") => {
+                let mark = match params.get(1).and_then(|mark| mark.first()) {
+                    Some(b'A') => ShellMark::PromptStart,
+                    Some(b'B') => ShellMark::CommandStart,
+                    Some(b'C') => ShellMark::CommandExecuted,
+                    Some(b'D') => ShellMark::CommandFinished(
+                        params
+                            .get(2)
+                            .and_then(|code| std::str::from_utf8(code).ok())
+                            .and_then(|code| code.parse().ok()),
+                    ),
+                    _ => return,
+                };
+                self.found.push(Sequence::ShellMark(mark));
+                return;
+            }
+            Some(b"8") => {
+                if params.get(2).is_some_and(|uri| !uri.is_empty()) {
+                    self.found.push(Sequence::Hyperlink);
+                }
+                return;
+            }
+            Some(b"777") if params.get(1) == Some(&&b"notify"[..]) => {
+                let title = params
+                    .get(2)
+                    .filter(|title| !title.is_empty())
+                    .map(|title| String::from_utf8_lossy(title).into_owned());
+                let message = match (title, text(3)) {
+                    (Some(title), Some(body)) => Some(format!("{}: {}", title, body)),
+                    (title, body) => body.or(title),
+                };
+                self.found
+                    .push(Sequence::Report(ReportedState::Input, message));
+                return;
+            }
+            _ => {}
+        }
+        let code = match params.first() {
+            Some(&b"10") => 10,
+            Some(&b"11") => 11,
+            _ => return,
+        };
+        if params.get(1) == Some(&&b"?"[..]) {
+            self.found.push(Sequence::Query(Query::Color {
+                code,
+                bell: bell_terminated,
+            }));
+        }
+    }
+}
+
+/// `OSC 9 ; 4 ; state ; percent`: 0 removes the progress, 1 sets it, 2 is an error, 3 is
+/// indeterminate and 4 paused.
+fn progress(params: &[&[u8]]) -> Option<Progress> {
+    let number = |idx: usize| {
+        params
+            .get(idx)
+            .and_then(|param| std::str::from_utf8(param).ok())
+            .and_then(|param| param.parse::<u8>().ok())
+            .unwrap_or(0)
+    };
+    let percent = number(3).min(100);
+    match number(2) {
+        1 => Some(Progress::Normal(percent)),
+        2 => Some(Progress::Error(percent)),
+        3 => Some(Progress::Indeterminate),
+        4 => Some(Progress::Paused(percent)),
+        _ => None,
+    }
+}
+
+pub struct SequenceScanner {
+    parser: vte::Parser,
+    collector: Collector,
+}
+
+impl SequenceScanner {
+    pub fn new() -> Self {
+        Self {
+            parser: vte::Parser::new(),
+            collector: Collector::default(),
+        }
+    }
+
+    pub fn scan(&mut self, data: &[u8]) -> Vec<Sequence> {
+        for &byte in data {
+            self.parser.advance(&mut self.collector, byte);
+        }
+        std::mem::take(&mut self.collector.found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan(data: &[u8]) -> Vec<Sequence> {
+        SequenceScanner::new().scan(data)
+    }
+
+    /// Feeds `data` in two reads split at every possible point.
+    fn scan_split(data: &[u8], expected: &[Sequence]) {
+        for at in 1..data.len() {
+            let mut scanner = SequenceScanner::new();
+            // vte ends an OSC at the ESC of its ST, so it may be found before the `\`.
+            let mut found = scanner.scan(&data[..at]);
+            found.extend(scanner.scan(&data[at..]));
+            assert_eq!(found, expected, "{:?} split at {at}", data);
+        }
+    }
+
+    #[test]
+    fn queries_split_across_reads() {
+        scan_split(b"\x1b[6n", &[Sequence::Query(Query::CursorPosition)]);
+        scan_split(
+            b"\x1b]11;?\x1b\\",
+            &[Sequence::Query(Query::Color {
+                code: 11,
+                bell: false,
+            })],
+        );
+        scan_split(
+            b"\x1b[?2026$p",
+            &[Sequence::Query(Query::PrivateMode(2026))],
+        );
+    }
+
+    #[test]
+    fn color_queries_remember_their_terminator() {
+        assert_eq!(
+            scan(b"\x1b]10;?\x07"),
+            [Sequence::Query(Query::Color {
+                code: 10,
+                bell: true
+            })]
+        );
+        assert_eq!(
+            scan(b"\x1b]11;?\x1b\\"),
+            [Sequence::Query(Query::Color {
+                code: 11,
+                bell: false
+            })]
+        );
+        // Setting a color isn't a query.
+        assert!(scan(b"\x1b]11;#000000\x07").is_empty());
+    }
+
+    #[test]
+    fn desktop_notifications() {
+        assert_eq!(
+            scan(b"\x1b]9;build done\x07"),
+            [Sequence::Report(
+                ReportedState::Input,
+                Some("build done".to_string())
+            )]
+        );
+        assert_eq!(
+            scan(b"\x1b]9;a;b\x1b\\"),
+            [Sequence::Report(
+                ReportedState::Input,
+                Some("a;b".to_string())
+            )]
+        );
+        assert_eq!(
+            scan(b"\x1b]777;notify;Claude;needs you\x07"),
+            [Sequence::Report(
+                ReportedState::Input,
+                Some("Claude: needs you".to_string())
+            )]
+        );
+        assert_eq!(
+            scan(b"\x1b]777;notify;;body only\x07"),
+            [Sequence::Report(
+                ReportedState::Input,
+                Some("body only".to_string())
+            )]
+        );
+        assert!(scan(b"\x1b]777;other;x\x07").is_empty());
+    }
+
+    #[test]
+    fn conemu_progress() {
+        assert_eq!(
+            scan(b"\x1b]9;4;1;42\x07"),
+            [Sequence::Progress(Some(Progress::Normal(42)))]
+        );
+        assert_eq!(
+            scan(b"\x1b]9;4;2;250\x07"),
+            [Sequence::Progress(Some(Progress::Error(100)))]
+        );
+        assert_eq!(
+            scan(b"\x1b]9;4;3\x07"),
+            [Sequence::Progress(Some(Progress::Indeterminate))]
+        );
+        assert_eq!(scan(b"\x1b]9;4;0\x07"), [Sequence::Progress(None)]);
+        // Other ConEmu commands are neither progress nor a message.
+        assert!(scan(b"\x1b]9;1;500\x07").is_empty());
+    }
+
+    #[test]
+    fn termoil_reports() {
+        assert_eq!(
+            scan(b"\x1b]7717;waiting;Bash: rm -rf target\x07"),
+            [Sequence::Report(
+                ReportedState::Waiting,
+                Some("Bash: rm -rf target".to_string())
+            )]
+        );
+        assert_eq!(
+            scan(b"\x1b]7717;idle\x1b\\"),
+            [Sequence::Report(ReportedState::Idle, None)]
+        );
+        assert!(scan(b"\x1b]7717;bogus\x07").is_empty());
+    }
+
+    #[test]
+    fn full_reset() {
+        assert_eq!(scan(b"\x1bc"), [Sequence::Reset]);
+        scan_split(b"ab\x1bc", &[Sequence::Reset]);
+        // `ESC ( c` designates a character set rather than resetting.
+        assert!(scan(b"\x1b(c").is_empty());
+    }
+
+    #[test]
+    fn shell_marks() {
+        assert_eq!(
+            scan(b"\x1b]133;A\x07\x1b]133;B\x07\x1b]133;C\x07"),
+            [
+                Sequence::ShellMark(ShellMark::PromptStart),
+                Sequence::ShellMark(ShellMark::CommandStart),
+                Sequence::ShellMark(ShellMark::CommandExecuted),
+            ]
+        );
+        assert_eq!(
+            scan(b"\x1b]133;D;2\x1b\\"),
+            [Sequence::ShellMark(ShellMark::CommandFinished(Some(2)))]
+        );
+        assert_eq!(
+            scan(b"\x1b]133;D\x07"),
+            [Sequence::ShellMark(ShellMark::CommandFinished(None))]
+        );
+    }
+}
@@ -0,0 +1,19 @@
+//! `termoil shell-init`: shell snippets that mark prompts and commands with OSC 133, so
+//! panes know for certain when the shell is at its prompt.
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl Shell {
+    pub fn init_script(self) -> &'static str {
+        match self {
+            Shell::Bash => include_str!("../shell/termoil.bash"),
+            Shell::Zsh => include_str!("../shell/termoil.zsh"),
+            Shell::Fish => include_str!("../shell/termoil.fish"),
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! What each pane is doing, as far as termoil can tell.
+
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaneState {
+    /// Spawned, but nothing has been printed yet.
+    Starting,
+    /// Producing output or running something, not at a prompt.
+    Working,
+    /// Sitting at a shell prompt.
+    Idle,
+    /// Asking to approve an action, e.g. `[Y/n]` or `Allow?`.
+    WaitingForApproval,
+    /// Asking for free-form input, e.g. a password.
+    WaitingForInput,
+    /// Working, but silent for longer than the stall threshold.
+    Stalled,
+    /// The child exited with a failure status or was killed.
+    Errored,
+    /// The child exited cleanly.
+    Exited,
+}
+
+impl PaneState {
+    pub fn label(self) -> &'static str {
+        match self {
+            PaneState::Starting => "starting",
+            PaneState::Working => "working",
+            PaneState::Idle => "idle",
+            PaneState::WaitingForApproval => "approval",
+            PaneState::WaitingForInput => "input",
+            PaneState::Stalled => "stalled",
+            PaneState::Errored => "errored",
+            PaneState::Exited => "exited",
+        }
+    }
+
+    pub fn icon(self) -> &'static str {
+        match self {
+            PaneState::Starting => "◌",
+            PaneState::Working => "●",
+            PaneState::Idle => "○",
+            PaneState::WaitingForApproval => "!",
+            PaneState::WaitingForInput => "?",
+            PaneState::Stalled => "⧗",
+            PaneState::Errored => "✗",
+            PaneState::Exited => "■",
+        }
+    }
+
+    /// States that put a pane in the attention queue.
+    pub fn needs_attention(self) -> bool {
+        matches!(
+            self,
+            PaneState::WaitingForApproval | PaneState::WaitingForInput | PaneState::Stalled
+        )
+    }
+}
+
+/// A command that ran past the `finished_after_secs` threshold and has completed.
+#[derive(Clone, Copy, Debug)]
+pub struct Finished {
+    /// Exit code, when the shell reported one with an OSC 133 mark.
+    pub exit: Option<i32>,
+    pub took: Duration,
+}
+
+impl Finished {
+    pub fn failed(self) -> bool {
+        self.exit.is_some_and(|code| code != 0)
+    }
+}
@@ -1,8 +1,17 @@
-use crate::{pty::Pane, App};
+use crate::{
+    emulator::{self, Cell},
+    notify,
+    pty::Pane,
+    sequences::Progress,
+    state::PaneState,
+    watchdog::Match,
+    App,
+};
 use ratatui::{
+    buffer,
     prelude::*,
     style::Modifier,
-    widgets::{Block, Borders, Paragraph},
+    widgets::{Block, BorderType, Borders, Paragraph},
 };
 
 const LOGO: &str = r#" _____                             ___________
@@ -10,12 +19,55 @@ const LOGO: &str = r#" _____                             ___________
  _  __/  _ \_  ___/_  __ `__ \  __ \_  /__  /
 / /_ /  __/  /   _  / / / / / /_/ /  / _  /
 \__/ \___//_/    /_/ /_/ /_/\____//_/  /_/"#;
+/// Default pane colors; also reported to children that query them (OSC 10/11).
+pub const TEXT_RGB: (u8, u8, u8) = (220, 220, 230);
+pub const BG_RGB: (u8, u8, u8) = (20, 15, 30);
 const CYAN: Color = Color::Rgb(80, 210, 255);
-const BG: Color = Color::Rgb(20, 15, 30);
+const TEXT: Color = Color::Rgb(TEXT_RGB.0, TEXT_RGB.1, TEXT_RGB.2);
+const BG: Color = Color::Rgb(BG_RGB.0, BG_RGB.1, BG_RGB.2);
 const STATUS_BG: Color = Color::Rgb(14, 11, 24);
 const DIM: Color = Color::Rgb(50, 45, 70);
 const ALERT: Color = Color::Rgb(255, 80, 80);
 const ALERT_DIM: Color = Color::Rgb(80, 30, 30);
+const AMBER: Color = Color::Rgb(255, 190, 80);
+const ORANGE: Color = Color::Rgb(255, 140, 60);
+const GREEN: Color = Color::Rgb(110, 220, 140);
+const MUTED: Color = Color::Rgb(120, 115, 140);
+const VIOLET: Color = Color::Rgb(200, 110, 255);
+
+fn progress_label(progress: Progress) -> String {
+    match progress {
+        Progress::Normal(percent) => format!("{}%", percent),
+        Progress::Error(percent) => format!("failed at {}%", percent),
+        Progress::Indeterminate => "in progress".to_string(),
+        Progress::Paused(percent) => format!("paused at {}%", percent),
+    }
+}
+
+fn state_color(state: PaneState) -> Color {
+    match state {
+        PaneState::Starting => MUTED,
+        PaneState::Working => GREEN,
+        PaneState::Idle => CYAN,
+        PaneState::WaitingForApproval => ALERT,
+        PaneState::WaitingForInput => AMBER,
+        PaneState::Stalled => ORANGE,
+        PaneState::Errored => VIOLET,
+        PaneState::Exited => MUTED,
+    }
+}
+
+/// How the grid shows a pane.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum GridPreview {
+    /// Resize each PTY to its grid cell, so the child reflows on every zoom toggle.
+    #[default]
+    Resize,
+    /// Keep the zoomed PTY size and show the part of the screen around the cursor.
+    Crop,
+    /// Keep the zoomed PTY size and show the whole screen sampled down to the cell.
+    Scale,
+}
 
 pub fn grid_dimensions(count: usize) -> (usize, usize) {
     match count {
@@ -67,20 +119,24 @@ pub fn compute_pane_areas(area: Rect, count: usize) -> Vec<Rect> {
     areas
 }
 
-pub fn draw(frame: &mut Frame, app: &App) {
-    let area = frame.area();
+fn split_status(area: Rect) -> (Rect, Rect) {
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([Constraint::Min(0), Constraint::Length(1)])
         .split(area);
+    (chunks[0], chunks[1])
+}
+
+pub fn draw(frame: &mut Frame, app: &App) {
+    let (main, status) = split_status(frame.area());
 
     if app.zoomed && !app.panes.is_empty() {
-        draw_zoomed(frame, app, chunks[0]);
+        draw_zoomed(frame, app, main);
     } else {
-        draw_grid(frame, app, chunks[0]);
+        draw_grid(frame, app, main);
     }
 
-    draw_status(frame, app, chunks[1]);
+    draw_status(frame, app, status);
 }
 
 fn pane_inner_area(area: Rect) -> Rect {
@@ -92,10 +148,10 @@ fn pane_inner_area(area: Rect) -> Rect {
     )
 }
 
-fn vt_color_to_tui(color: vt100::Color) -> Color {
+fn emulator_color_to_tui(color: emulator::Color, default: Color) -> Color {
     match color {
-        vt100::Color::Default => Color::Reset,
-        vt100::Color::Idx(idx) => match idx {
+        emulator::Color::Default => default,
+        emulator::Color::Idx(idx) => match idx {
             0 => Color::Black,
             1 => Color::Red,
             2 => Color::Green,
@@ -114,28 +170,40 @@ fn vt_color_to_tui(color: vt100::Color) -> Color {
             15 => Color::White,
             _ => Color::Indexed(idx),
         },
-        vt100::Color::Rgb(r, g, b) => Color::Rgb(r, g, b),
+        emulator::Color::Rgb(r, g, b) => Color::Rgb(r, g, b),
     }
 }
 
-fn vt_cell_style(cell: &vt100::Cell) -> Style {
+fn cell_style(cell: &Cell) -> Style {
     let mut style = Style::default()
-        .fg(vt_color_to_tui(cell.fgcolor()))
-        .bg(vt_color_to_tui(cell.bgcolor()));
+        .fg(emulator_color_to_tui(cell.fg, TEXT))
+        .bg(emulator_color_to_tui(cell.bg, BG));
 
     let mut modifier = Modifier::empty();
-    if cell.bold() {
+    if cell.bold {
         modifier.insert(Modifier::BOLD);
     }
-    if cell.italic() {
+    if cell.italic {
         modifier.insert(Modifier::ITALIC);
     }
-    if cell.underline() {
+    if cell.underline {
         modifier.insert(Modifier::UNDERLINED);
     }
-    if cell.inverse() {
+    if cell.inverse {
         modifier.insert(Modifier::REVERSED);
     }
+    if cell.dim {
+        modifier.insert(Modifier::DIM);
+    }
+    if cell.blink {
+        modifier.insert(Modifier::SLOW_BLINK);
+    }
+    if cell.strikethrough {
+        modifier.insert(Modifier::CROSSED_OUT);
+    }
+    if cell.hidden {
+        modifier.insert(Modifier::HIDDEN);
+    }
     if !modifier.is_empty() {
         style = style.add_modifier(modifier);
     }
@@ -143,33 +211,135 @@ fn vt_cell_style(cell: &vt100::Cell) -> Style {
     style
 }
 
-fn render_pane_cells(frame: &mut Frame, pane: &Pane, area: Rect) {
+fn render_pane_cells(
+    frame: &mut Frame,
+    pane: &Pane,
+    area: Rect,
+    preview: GridPreview,
+    highlight: Option<&Match>,
+) {
     let inner = pane_inner_area(area);
     if inner.width == 0 || inner.height == 0 {
         return;
     }
 
+    let (rows, cols) = pane.size();
+    let fits = rows <= inner.height && cols <= inner.width;
+    let scaled = preview == GridPreview::Scale && !fits;
+    // Crop to a window that keeps the cursor in view.
+    let (row_offset, col_offset) = if preview == GridPreview::Crop && !fits {
+        let (cursor_row, cursor_col) = pane.cursor_position();
+        (
+            (cursor_row + 1)
+                .saturating_sub(inner.height)
+                .min(rows.saturating_sub(inner.height)),
+            (cursor_col + 1)
+                .saturating_sub(inner.width)
+                .min(cols.saturating_sub(inner.width)),
+        )
+    } else {
+        (0, 0)
+    };
+
     let buffer = frame.buffer_mut();
+    let sample_col = |x: u16| (x as u32 * cols as u32 / inner.width as u32) as u16;
     for y in 0..inner.height {
+        let row = if scaled {
+            (y as u32 * rows as u32 / inner.height as u32) as u16
+        } else {
+            y + row_offset
+        };
         for x in 0..inner.width {
+            buffer[(inner.x + x, inner.y + y)].reset();
+        }
+        let highlighted = |col: u16| {
+            highlight.is_some_and(|found| {
+                row == found.row && col >= found.col && col < found.col + found.len
+            })
+        };
+        let mut put = |x: u16, col: u16, src: &Cell| {
             let cell = &mut buffer[(inner.x + x, inner.y + y)];
-            if let Some(src) = pane.cell(y, x) {
-                if src.is_wide_continuation() {
-                    cell.set_symbol(" ");
-                } else {
-                    let symbol = src.contents();
-                    if symbol.is_empty() {
-                        cell.set_symbol(" ");
-                    } else {
-                        cell.set_symbol(&symbol);
-                    }
-                }
-                cell.set_style(vt_cell_style(src));
+            // Sampling can land on either half of a wide character, and a lone wide
+            // character would spill into the next cell.
+            if src.wide_continuation || src.symbol.is_empty() || scaled && src.wide {
+                cell.set_symbol(" ");
+            } else {
+                cell.set_symbol(src.symbol);
+            }
+            if highlighted(col) {
+                cell.set_style(Style::default().fg(BG).bg(AMBER));
             } else {
-                cell.reset();
+                cell.set_style(cell_style(src));
+            }
+        };
+        if scaled {
+            // Columns map to grid cells in order, so one pass over the row finds every sample.
+            let mut x = 0;
+            pane.visit_row(row, 0, cols, |col, src| {
+                while x < inner.width && sample_col(x) == col {
+                    put(x, col, src);
+                    x += 1;
+                }
+            });
+        } else {
+            pane.visit_row(row, col_offset, col_offset + inner.width, |col, src| {
+                put(col - col_offset, col, src)
+            });
+        }
+    }
+}
+
+/// Copies of the zoomed pane's hyperlinked cells from the finished frame, with each run of
+/// cells sharing a target wrapped in OSC 8. ratatui can't carry the sequences through its
+/// buffer (they'd count toward the cell width), so these are written to the host after the
+/// frame to keep links clickable.
+pub fn hyperlink_cells(app: &App, buffer: &Buffer) -> Vec<(u16, u16, buffer::Cell)> {
+    let mut cells = Vec::new();
+    if !app.zoomed {
+        return cells;
+    }
+    let Some(pane) = app.panes.get(app.selected).map(|slot| &slot.pane) else {
+        return cells;
+    };
+    if !pane.has_hyperlinks() {
+        return cells;
+    }
+    let inner = pane_inner_area(split_status(buffer.area).0);
+    for y in 0..inner.height {
+        let mut open: Option<String> = None;
+        pane.visit_row(y, 0, inner.width, |x, src| {
+            if src.wide_continuation {
+                return;
+            }
+            let link = src.hyperlink;
+            let mut cell = buffer[(inner.x + x, inner.y + y)].clone();
+            if open.as_deref() != link {
+                if open.is_some() {
+                    close_hyperlink(&mut cells);
+                }
+                open = link.map(str::to_string);
+                if let Some(uri) = link {
+                    let uri: String = uri.chars().filter(|c| !c.is_control()).collect();
+                    let symbol = format!("\x1b]8;;{uri}\x1b\\{}", cell.symbol());
+                    cell.set_symbol(&symbol);
+                }
             }
+            if open.is_some() {
+                cells.push((inner.x + x, inner.y + y, cell));
+            }
+        });
+        if open.is_some() {
+            close_hyperlink(&mut cells);
         }
     }
+    cells
+}
+
+fn close_hyperlink(cells: &mut [(u16, u16, buffer::Cell)]) {
+    if let Some((_, _, cell)) = cells.last_mut() {
+        let symbol = format!("{}\x1b]8;;\x1b\\", cell.symbol());
+        cell.set_symbol(&symbol);
+    }
 }
 
 fn draw_status(frame: &mut Frame, app: &App, area: Rect) {
@@ -186,17 +356,25 @@ fn draw_status(frame: &mut Frame, app: &App, area: Rect) {
     let queue = app.attention_queue();
     let next = queue
         .first()
-        .map(|idx| (idx + 1).to_string())
+        .map(|&idx| match app.panes[idx].waiting_for() {
+            Some(waited) => format!("{} waiting {}", idx + 1, notify::format_wait(waited)),
+            None => (idx + 1).to_string(),
+        })
         .unwrap_or_else(|| "-".to_string());
+    let state = app
+        .panes
+        .get(app.selected)
+        .map_or("-", |slot| slot.state.label());
     let mouse = if app.mouse_capture_enabled {
         "on"
     } else {
         "off"
     };
     let text = format!(
-        " mode:{} pane:{} attention:{} next:{} mouse:{} | n:new x:close r:restart 1-9:open [ ]:queue a:ack Enter:zoom Ctrl+Space:back F2:mouse q:quit ",
+        " mode:{} pane:{} state:{} attention:{} next:{} mouse:{} | n:new x:close r:restart 1-9:open [ ]:queue a:ack b:bell Enter:zoom Ctrl+Space:back F2:mouse q:quit ",
         mode,
         pane,
+        state,
         queue.len(),
         next,
         mouse
@@ -241,13 +419,18 @@ fn draw_grid(frame: &mut Frame, app: &App, area: Rect) {
     }
 
     let pane_areas = compute_pane_areas(chunks[1], app.panes.len());
+    let blink_on = app.blink_on();
 
-    for (i, pane) in app.panes.iter().enumerate() {
+    for (i, slot) in app.panes.iter().enumerate() {
+        let pane = &slot.pane;
         let is_selected = i == app.selected;
-        let needs_attention = app.attention.get(i).copied().unwrap_or(false);
-        let blink_on = (app.tick / 5) % 2 == 0;
+        let needs_attention = slot.attention;
+        let finished = slot.finished;
 
-        let border_color = if needs_attention && is_selected {
+        let flashing = slot.bell_flashing;
+        let border_color = if flashing {
+            AMBER
+        } else if needs_attention && is_selected {
             if blink_on {
                 ALERT
             } else {
@@ -261,29 +444,99 @@ fn draw_grid(frame: &mut Frame, app: &App, area: Rect) {
             }
         } else if is_selected {
             CYAN
+        } else if let Some(done) = finished {
+            if done.failed() {
+                ORANGE
+            } else {
+                GREEN
+            }
         } else {
             DIM
         };
 
-        let title = if needs_attention {
-            format!(" shell {} [!] ", i + 1)
+        let escalated = needs_attention && slot.is_escalated();
+        let state = slot.state;
+        let mut status = format!("{} {} ", state.icon(), state.label());
+        if let Some(detail) = slot.report_detail() {
+            status.push_str(&format!("· {} ", detail));
+        } else if let Some(found) = slot.current_match() {
+            status.push_str(&format!("[{}] ", found.rule));
+        }
+        if let Some(code) = pane.last_command_exit().filter(|&code| code != 0) {
+            if state == PaneState::Idle && finished.is_none() {
+                status.push_str(&format!("· exit {} ", code));
+            }
+        }
+        if let Some(progress) = pane.progress() {
+            status.push_str(&format!("· {} ", progress_label(progress)));
+        }
+        if slot.bell_rang() {
+            status.push_str("· bell ");
+        } else if slot.bell_muted {
+            status.push_str("· bell off ");
+        }
+        if let Some(waited) = slot.waiting_for().filter(|_| needs_attention) {
+            status.push_str(&format!("· waiting {} ", notify::format_wait(waited)));
+        }
+        let status_style = if escalated {
+            Style::default()
+                .fg(BG)
+                .bg(ALERT)
+                .add_modifier(Modifier::BOLD)
         } else {
-            format!(" shell {} ", i + 1)
+            Style::default().fg(state_color(state))
+        };
+        let finished_span = finished.map(|done| {
+            let took = notify::format_wait(done.took);
+            match done.exit {
+                Some(code) if done.failed() => Span::styled(
+                    format!("✗ exit {} after {} ", code, took),
+                    Style::default().fg(ORANGE).add_modifier(Modifier::BOLD),
+                ),
+                _ => Span::styled(
+                    format!("✓ done in {} ", took),
+                    Style::default().fg(GREEN).add_modifier(Modifier::BOLD),
+                ),
+            }
+        });
+        let name = match slot.profile {
+            Some(profile) => format!(" shell {} · {} ", i + 1, app.config.profiles[profile].name),
+            None => format!(" shell {} ", i + 1),
         };
+        let mut title = Line::from(vec![
+            Span::styled(name, Style::default().fg(CYAN)),
+            Span::styled(status, status_style),
+        ]);
+        if let Some(span) = finished_span {
+            title.push_span(span);
+        }
 
+        let border_type = if escalated || flashing {
+            BorderType::Thick
+        } else {
+            BorderType::Plain
+        };
         let block = Block::default()
-            .title(Line::from(title).style(Style::default().fg(CYAN)))
+            .title(title)
             .borders(Borders::ALL)
+            .border_type(border_type)
             .border_style(Style::default().fg(border_color))
             .style(Style::default().bg(BG));
 
         frame.render_widget(block, pane_areas[i]);
-        render_pane_cells(frame, pane, pane_areas[i]);
+        render_pane_cells(
+            frame,
+            pane,
+            pane_areas[i],
+            app.grid_preview,
+            slot.current_match(),
+        );
     }
 }
 
 fn draw_zoomed(frame: &mut Frame, app: &App, area: Rect) {
-    let pane = &app.panes[app.selected];
+    let slot = &app.panes[app.selected];
+    let pane = &slot.pane;
     let mouse_mode = if app.mouse_capture_enabled {
         "mouse:on"
     } else {
@@ -302,7 +555,7 @@ fn draw_zoomed(frame: &mut Frame, app: &App, area: Rect) {
         .style(Style::default().bg(BG));
 
     frame.render_widget(block, area);
-    render_pane_cells(frame, pane, area);
+    render_pane_cells(frame, pane, area, app.grid_preview, slot.current_match());
 
     let (row, col) = pane.cursor_position();
     let inner = pane_inner_area(area);
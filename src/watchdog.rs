@@ -1,23 +1,272 @@
-use regex::Regex;
+use crate::config::{RuleConfig, RuleScope, RuleState};
+use crate::emulator::TerminalEmulator;
+use crate::state::PaneState;
+use anyhow::{Context, Result};
+use regex::{Regex, RegexBuilder, RegexSet, RegexSetBuilder};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
+/// Rows the watchdog inspects: the cursor row plus the five above it.
+pub const CONTEXT_ROWS: u16 = 6;
+
+/// The part of a pane's screen the watchdog looks at.
+#[derive(Default)]
+pub struct WatchContext {
+    /// Text of the cursor row.
+    pub cursor_line: String,
+    /// Text of the rows from `first_row` through the cursor row, one line per row except
+    /// where a row soft-wraps into the next.
+    pub nearby: String,
+    pub first_row: u16,
+    /// Text of the bottom `Reach::bottom_rows` rows, starting at `bottom_first_row`.
+    pub bottom: String,
+    pub bottom_first_row: u16,
+    /// The whole visible screen, when `Reach::whole_screen` asked for it.
+    pub screen: String,
+    pub alternate_screen: bool,
+    /// Whether the shell is at its prompt, when it says so with OSC 133 marks; otherwise
+    /// the watchdog guesses from the cursor row.
+    pub at_prompt: Option<bool>,
+    /// Screen width, to place soft-wrapped lines.
+    pub cols: u16,
+}
+
+/// How much of the screen a rule set reads beyond the rows near the cursor, so panes only
+/// extract what's needed.
+#[derive(Clone, Copy, Default)]
+pub struct Reach {
+    pub bottom_rows: u16,
+    pub whole_screen: bool,
+}
+
+/// The cursor row and the rows just above it, as the watchdog sees them, plus whatever else
+/// `reach` asks for. Only these rows are extracted, rather than rendering the whole screen to
+/// a string.
+pub fn watch_context(
+    emulator: &dyn TerminalEmulator,
+    rows: u16,
+    cols: u16,
+    reach: Reach,
+) -> WatchContext {
+    let cursor_row = emulator.cursor_position().0;
+    let first_row = cursor_row.saturating_sub(CONTEXT_ROWS - 1);
+    let nearby = emulator.rows_text(first_row, cursor_row);
+    let cursor_line = nearby.rsplit('\n').next().unwrap_or("").to_string();
+    let bottom_first_row = rows.saturating_sub(reach.bottom_rows);
+    let bottom = if reach.bottom_rows > 0 {
+        emulator.rows_text(bottom_first_row, rows.saturating_sub(1))
+    } else {
+        String::new()
+    };
+    let screen = if reach.whole_screen {
+        emulator.contents()
+    } else {
+        String::new()
+    };
+    WatchContext {
+        cursor_line,
+        nearby,
+        first_row,
+        bottom,
+        bottom_first_row,
+        screen,
+        alternate_screen: emulator.alternate_screen(),
+        at_prompt: None,
+        cols,
+    }
+}
+
+impl WatchContext {
+    /// Screen `(row, col)` of a byte offset into `text`, which starts at `first_row`. Columns
+    /// count display cells, and lines wrap where the emulator wraps them: a wide character that
+    /// doesn't fit at the end of a row starts the next one.
+    fn position(&self, text: &str, first_row: u16, offset: usize) -> (u16, u16) {
+        let cols = self.cols.max(1) as usize;
+        let mut row = first_row as usize;
+        let mut col = 0;
+        for (idx, ch) in text.char_indices() {
+            if ch == '\n' {
+                if idx >= offset {
+                    break;
+                }
+                row += 1;
+                col = 0;
+                continue;
+            }
+            let width = ch.width().unwrap_or(0);
+            if col + width > cols {
+                row += 1;
+                col = 0;
+            }
+            if idx >= offset {
+                break;
+            }
+            col += width;
+        }
+        if col >= cols {
+            row += 1;
+            col = 0;
+        }
+        (row as u16, col as u16)
+    }
+}
+
+/// Compiles a rule pattern. Rules read several rows at once, so `^` and `$` anchor lines
+/// rather than the whole text.
+fn compile(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern).multi_line(true).build()
+}
+
+/// A named pattern and the state it indicates.
+#[derive(Clone)]
+struct Rule {
+    name: String,
+    state: PaneState,
+    regex: Regex,
+    scope: RuleScope,
+    alternate_screen: Option<bool>,
+    unless: Vec<Regex>,
+}
+
+impl Rule {
+    fn new(name: &str, pattern: &str, state: PaneState) -> Option<Self> {
+        Some(Self {
+            name: name.to_string(),
+            state,
+            regex: compile(pattern).ok()?,
+            scope: RuleScope::Nearby,
+            alternate_screen: None,
+            unless: Vec::new(),
+        })
+    }
+
+    fn from_config(config: &RuleConfig) -> Result<Self> {
+        let compile = |pattern: &str| {
+            compile(pattern).with_context(|| format!("rule `{}`: bad pattern", config.name))
+        };
+        Ok(Self {
+            name: config.name.clone(),
+            state: match config.state {
+                RuleState::Approval => PaneState::WaitingForApproval,
+                RuleState::Input => PaneState::WaitingForInput,
+            },
+            regex: compile(&config.pattern)?,
+            scope: config.scope,
+            alternate_screen: config.alternate_screen,
+            unless: config
+                .unless
+                .iter()
+                .map(|pattern| compile(pattern))
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    /// Where this rule matches `context`, if it applies there.
+    fn find(&self, context: &WatchContext) -> Option<Match> {
+        if self
+            .alternate_screen
+            .is_some_and(|alternate| alternate != context.alternate_screen)
+        {
+            return None;
+        }
+        let (found, (row, col)) = match self.scope {
+            RuleScope::Cursor => {
+                let found = self.regex.find(&context.cursor_line)?;
+                let start = context
+                    .nearby
+                    .len()
+                    .saturating_sub(context.cursor_line.len());
+                let position =
+                    context.position(&context.nearby, context.first_row, start + found.start());
+                (found, position)
+            }
+            RuleScope::Nearby => {
+                let found = self.regex.find(&context.nearby)?;
+                let position = context.position(&context.nearby, context.first_row, found.start());
+                (found, position)
+            }
+            RuleScope::Bottom(rows) => {
+                // The context holds the rows wanted by the widest bottom rule; trim to ours.
+                let lines: Vec<&str> = context.bottom.split('\n').collect();
+                let skip = lines.len().saturating_sub(rows as usize);
+                let start: usize = lines[..skip].iter().map(|line| line.len() + 1).sum();
+                let text = &context.bottom[start.min(context.bottom.len())..];
+                let found = self.regex.find(text)?;
+                let position = context.position(
+                    &context.bottom,
+                    context.bottom_first_row,
+                    start + found.start(),
+                );
+                (found, position)
+            }
+        };
+        if self.unless.iter().any(|re| re.is_match(&context.screen)) {
+            return None;
+        }
+        Some(Match {
+            rule: self.name.clone(),
+            pattern: self.regex.as_str().to_string(),
+            state: self.state,
+            text: found.as_str().to_string(),
+            row,
+            col,
+            len: found.as_str().width() as u16,
+        })
+    }
+}
+
+/// Why the watchdog flagged a screen.
+#[derive(Clone, Debug)]
+pub struct Match {
+    pub rule: String,
+    pub pattern: String,
+    pub state: PaneState,
+    pub text: String,
+    /// Screen position of the start of the match.
+    pub row: u16,
+    pub col: u16,
+    /// Width of the match in screen cells.
+    pub len: u16,
+}
+
+#[derive(Clone)]
 pub struct Watchdog {
-    patterns: Vec<Regex>,
+    rules: Vec<Rule>,
+    /// All rule patterns, so text near the cursor with no match is rejected in one pass.
+    rule_set: RegexSet,
     prompt_patterns: Vec<Regex>,
 }
 
 impl Watchdog {
     pub fn new() -> Self {
-        let pattern_strs = [
-            r"(?i)\[y/n\]",
-            r"(?i)\(y/n\)",
-            r"(?i)password:",
-            r"(?i)allow\?",
-            r"(?i)proceed\?",
-            r"(?i)continue\?",
-            r"(?i)do you want to",
-            r"(?i)are you sure",
-            r"(?i)\[yes/no\]",
-            r"(?i)Esc to cancel",
+        let rules = [
+            ("y/n", r"(?i)\[y/n\]", PaneState::WaitingForApproval),
+            ("(y/n)", r"(?i)\(y/n\)", PaneState::WaitingForApproval),
+            ("allow", r"(?i)allow\?", PaneState::WaitingForApproval),
+            ("proceed", r"(?i)proceed\?", PaneState::WaitingForApproval),
+            ("continue", r"(?i)continue\?", PaneState::WaitingForApproval),
+            (
+                "do-you-want",
+                r"(?i)do you want to",
+                PaneState::WaitingForApproval,
+            ),
+            (
+                "are-you-sure",
+                r"(?i)are you sure",
+                PaneState::WaitingForApproval,
+            ),
+            ("yes/no", r"(?i)\[yes/no\]", PaneState::WaitingForApproval),
+            (
+                "esc-to-cancel",
+                r"(?i)Esc to cancel",
+                PaneState::WaitingForApproval,
+            ),
+            ("password", r"(?i)password:", PaneState::WaitingForInput),
+            ("passphrase", r"(?i)passphrase", PaneState::WaitingForInput),
+            (
+                "press-enter",
+                r"(?i)press enter to continue",
+                PaneState::WaitingForInput,
+            ),
         ];
 
         let prompt_strs = [
@@ -28,27 +277,334 @@ impl Watchdog {
             r"^\s*\w+@",
         ];
 
-        Self {
-            patterns: pattern_strs
+        // The built-in patterns are known to compile.
+        Self::with_rule_list(
+            rules
                 .iter()
-                .filter_map(|p| Regex::new(p).ok())
+                .filter_map(|(name, pattern, state)| Rule::new(name, pattern, *state))
                 .collect(),
-            prompt_patterns: prompt_strs
+            prompt_strs
                 .iter()
                 .filter_map(|p| Regex::new(p).ok())
                 .collect(),
+        )
+        .expect("built-in watchdog rules")
+    }
+
+    fn with_rule_list(rules: Vec<Rule>, prompt_patterns: Vec<Regex>) -> Result<Self> {
+        // Only nearby rules read the same text as the set; the others are checked one by one.
+        let nearby = rules
+            .iter()
+            .filter(|rule| matches!(rule.scope, RuleScope::Nearby));
+        let rule_set = RegexSetBuilder::new(nearby.map(|rule| rule.regex.as_str()))
+            .multi_line(true)
+            .build()
+            .context("watchdog rules don't fit in one pattern set")?;
+        Ok(Self {
+            rules,
+            rule_set,
+            prompt_patterns,
+        })
+    }
+
+    /// This rule set with `disabled` rules dropped, and `rules` replacing same-named rules or
+    /// appended after the rest.
+    pub fn with_rules(self, rules: &[RuleConfig], disabled: &[String]) -> Result<Self> {
+        let mut list: Vec<Rule> = self
+            .rules
+            .into_iter()
+            .filter(|rule| !disabled.contains(&rule.name))
+            .collect();
+        for config in rules {
+            let rule = Rule::from_config(config)?;
+            match list.iter_mut().find(|existing| existing.name == rule.name) {
+                Some(existing) => *existing = rule,
+                None => list.push(rule),
+            }
+        }
+        Self::with_rule_list(list, self.prompt_patterns)
+    }
+
+    /// The parts of the screen this rule set reads.
+    pub fn reach(&self) -> Reach {
+        let mut reach = Reach::default();
+        for rule in &self.rules {
+            if let RuleScope::Bottom(rows) = rule.scope {
+                reach.bottom_rows = reach.bottom_rows.max(rows);
+            }
+            reach.whole_screen |= !rule.unless.is_empty();
         }
+        reach
     }
 
-    pub fn needs_attention(&self, cursor_line: &str, nearby_text: &str) -> bool {
-        if self.is_shell_prompt(cursor_line) {
-            return false;
+    /// The first rule (in rule order) matching in its part of the screen, unless the cursor
+    /// sits at a shell prompt.
+    pub fn needs_attention(&self, context: &WatchContext) -> Option<Match> {
+        if self.is_shell_prompt(context) {
+            return None;
         }
-        self.patterns.iter().any(|re| re.is_match(nearby_text))
+        let near_cursor = self.rule_set.matches(&context.nearby);
+        let mut nearby_idx = 0;
+        self.rules.iter().find_map(|rule| {
+            if matches!(rule.scope, RuleScope::Nearby) {
+                nearby_idx += 1;
+                if !near_cursor.matched(nearby_idx - 1) {
+                    return None;
+                }
+            }
+            rule.find(context)
+        })
     }
 
-    fn is_shell_prompt(&self, line: &str) -> bool {
-        let trimmed = line.trim();
+    /// Every rule matching in its part of the screen, in rule order, unless the cursor sits
+    /// at a shell prompt. The first is the one `needs_attention` finds.
+    pub fn matches(&self, context: &WatchContext) -> Vec<Match> {
+        if self.is_shell_prompt(context) {
+            return Vec::new();
+        }
+        self.rules
+            .iter()
+            .filter_map(|rule| rule.find(context))
+            .collect()
+    }
+
+    /// Best guess at what the pane is doing, with the match behind a waiting state.
+    pub fn classify(&self, context: &WatchContext) -> (PaneState, Option<Match>) {
+        if self.is_shell_prompt(context) {
+            return (PaneState::Idle, None);
+        }
+        match self.needs_attention(context) {
+            Some(found) => (found.state, Some(found)),
+            None => (PaneState::Working, None),
+        }
+    }
+
+    fn is_shell_prompt(&self, context: &WatchContext) -> bool {
+        if let Some(at_prompt) = context.at_prompt {
+            return at_prompt;
+        }
+        let trimmed = context.cursor_line.trim();
         !trimmed.is_empty() && self.prompt_patterns.iter().any(|re| re.is_match(trimmed))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A watchdog with only the rules in `json`, a list of rule configs.
+    fn watchdog(json: &str) -> Watchdog {
+        let builtin = Watchdog::new();
+        let names: Vec<String> = builtin.rules.iter().map(|rule| rule.name.clone()).collect();
+        let rules: Vec<RuleConfig> = serde_json::from_str(json).unwrap();
+        builtin.with_rules(&rules, &names).unwrap()
+    }
+
+    /// Rows ending at the cursor, starting at screen row `first_row`.
+    fn context(rows: &[&str], first_row: u16) -> WatchContext {
+        WatchContext {
+            cursor_line: rows.last().unwrap().to_string(),
+            nearby: rows.join("\n"),
+            first_row,
+            cols: 80,
+            ..WatchContext::default()
+        }
+    }
+
+    fn with_bottom(mut context: WatchContext, rows: &[&str], first_row: u16) -> WatchContext {
+        context.bottom = rows.join("\n");
+        context.bottom_first_row = first_row;
+        context
+    }
+
+    fn found(watchdog: &Watchdog, context: &WatchContext) -> Option<(String, u16, u16)> {
+        watchdog
+            .needs_attention(context)
+            .map(|found| (found.rule, found.row, found.col))
+    }
+
+    #[test]
+    fn positions_count_display_cells() {
+        let mut context = context(&[""], 0);
+        context.cols = 10;
+        let at = |text: &str, needle: &str| context.position(text, 5, text.find(needle).unwrap());
+        assert_eq!(at("ab\n確認 ok", "ok"), (6, 5));
+        assert_eq!(at("✅ done", "done"), (5, 3));
+        // Ten cells fill the row exactly; the next character starts the row below.
+        assert_eq!(at("0123456789x", "x"), (6, 0));
+        assert_eq!(at("0123456789\nx", "x"), (6, 0));
+        assert_eq!(at("0123456789x\ny", "y"), (7, 0));
+        // A wide character that would straddle the edge moves to the next row whole.
+        assert_eq!(at("012345678確認", "確"), (6, 0));
+        assert_eq!(at("012345678確認\nz", "z"), (7, 0));
+    }
+
+    #[test]
+    fn matches_land_on_the_emulators_cells() {
+        use crate::emulator::{self, EmulatorKind};
+        use clap::ValueEnum;
+
+        let watchdog = watchdog(
+            r#"[{"name": "proceed", "pattern": "Proceed\\?", "state": "approval", "scope": "cursor"}]"#,
+        );
+        for &kind in EmulatorKind::value_variants() {
+            let mut emulator = emulator::new_emulator(kind, 6, 20, 0);
+            // Wide characters before the match, and a wide character that wraps early.
+            emulator.process("编辑文件 main.rs\r\n0123456789012345678确认 Proceed?".as_bytes());
+            let context = watch_context(emulator.as_ref(), 6, 20, Reach::default());
+            let found = watchdog.needs_attention(&context).unwrap();
+            assert_eq!((found.row, found.col, found.len), (2, 5, 8), "{kind:?}");
+            let mut symbols = String::new();
+            emulator.visit_row(
+                found.row,
+                found.col,
+                found.col + found.len,
+                &mut |_, cell| symbols.push_str(cell.symbol),
+            );
+            assert_eq!(symbols, "Proceed?", "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn cursor_rules_read_only_the_cursor_row() {
+        let watchdog = watchdog(
+            r#"[{"name": "proceed", "pattern": "^Proceed\\?", "state": "approval", "scope": "cursor"}]"#,
+        );
+        let waiting = context(&["Compiling foo", "Compiling bar", "Proceed? [y/n]"], 10);
+        assert_eq!(
+            found(&watchdog, &waiting),
+            Some(("proceed".to_string(), 12, 0))
+        );
+        let scrolled = context(&["Proceed? [y/n]", "y", "Compiling foo"], 10);
+        assert_eq!(found(&watchdog, &scrolled), None);
+    }
+
+    #[test]
+    fn nearby_rules_anchor_at_any_row() {
+        let watchdog =
+            watchdog(r#"[{"name": "menu", "pattern": "^  1\\. Yes$", "state": "approval"}]"#);
+        let waiting = context(
+            &[
+                "Allow this edit?",
+                "  1. Yes",
+                "  2. No",
+                "Enter to confirm",
+            ],
+            20,
+        );
+        assert_eq!(
+            found(&watchdog, &waiting),
+            Some(("menu".to_string(), 21, 0))
+        );
+        assert_eq!(
+            found(&watchdog, &context(&["  1. Yes, please", ""], 0)),
+            None
+        );
+    }
+
+    #[test]
+    fn bottom_rules_read_their_own_rows() {
+        let watchdog = watchdog(
+            r#"[{"name": "continue", "pattern": "^Continue now", "state": "input", "scope": {"bottom": 2}}]"#,
+        );
+        let cursor = context(&["working"], 5);
+        let last = with_bottom(context(&["working"], 5), &["a", "b", "Continue now?"], 37);
+        assert_eq!(
+            found(&watchdog, &last),
+            Some(("continue".to_string(), 39, 0))
+        );
+        // Three rows up is outside this rule's two, even though the context holds more.
+        let above = with_bottom(cursor, &["Continue now?", "b", "c"], 37);
+        assert_eq!(found(&watchdog, &above), None);
+    }
+
+    #[test]
+    fn unless_and_alternate_screen_suppress_rules() {
+        let watchdog = watchdog(
+            r#"[{"name": "yes", "pattern": "Yes", "state": "approval",
+                 "alternate_screen": true, "unless": ["^diff --git"]}]"#,
+        );
+        let mut waiting = context(&["Yes"], 0);
+        waiting.alternate_screen = true;
+        waiting.screen = "header\nYes".to_string();
+        assert!(found(&watchdog, &waiting).is_some());
+
+        waiting.screen = "header\ndiff --git a/x b/x\nYes".to_string();
+        assert_eq!(found(&watchdog, &waiting), None);
+        // Only at the start of a line.
+        waiting.screen = "see diff --git\nYes".to_string();
+        assert!(found(&watchdog, &waiting).is_some());
+
+        waiting.alternate_screen = false;
+        assert_eq!(found(&watchdog, &waiting), None);
+    }
+
+    #[test]
+    fn with_rules_replaces_and_disables() {
+        let rules: Vec<RuleConfig> = serde_json::from_str(
+            r#"[{"name": "proceed", "pattern": "Proceed\\? \\(yes\\)", "state": "input"},
+                {"name": "custom", "pattern": "Go on\\?", "state": "approval"}]"#,
+        )
+        .unwrap();
+        let watchdog = Watchdog::new()
+            .with_rules(&rules, &["y/n".to_string()])
+            .unwrap();
+
+        assert_eq!(found(&watchdog, &context(&["Overwrite? [y/n]"], 0)), None);
+        assert_eq!(found(&watchdog, &context(&["Proceed?"], 0)), None);
+        let replaced = watchdog
+            .needs_attention(&context(&["Proceed? (yes)"], 0))
+            .unwrap();
+        assert_eq!(replaced.rule, "proceed");
+        assert_eq!(replaced.state, PaneState::WaitingForInput);
+        assert_eq!(
+            found(&watchdog, &context(&["Go on?"], 0)).map(|found| found.0),
+            Some("custom".to_string())
+        );
+        // Replacing keeps the rule's place; new rules go last.
+        let names: Vec<&str> = watchdog
+            .rules
+            .iter()
+            .map(|rule| rule.name.as_str())
+            .collect();
+        assert_eq!(names.last(), Some(&"custom"));
+        assert!(
+            names.iter().position(|&name| name == "proceed")
+                < names.iter().position(|&name| name == "continue")
+        );
+    }
+
+    #[test]
+    fn bad_patterns_are_errors() {
+        let rules: Vec<RuleConfig> =
+            serde_json::from_str(r#"[{"name": "broken", "pattern": "(", "state": "input"}]"#)
+                .unwrap();
+        assert!(Watchdog::new().with_rules(&rules, &[]).is_err());
+    }
+
+    #[test]
+    fn reach_covers_every_rule() {
+        assert!(!Watchdog::new().reach().whole_screen);
+        assert_eq!(Watchdog::new().reach().bottom_rows, 0);
+        let watchdog = watchdog(
+            r#"[{"name": "a", "pattern": "a", "state": "input", "scope": {"bottom": 2}},
+                {"name": "b", "pattern": "b", "state": "input", "scope": {"bottom": 5}},
+                {"name": "c", "pattern": "c", "state": "input", "unless": ["d"]}]"#,
+        );
+        let reach = watchdog.reach();
+        assert_eq!(reach.bottom_rows, 5);
+        assert!(reach.whole_screen);
+    }
+
+    #[test]
+    fn shell_prompts_are_idle() {
+        let watchdog = Watchdog::new();
+        let prompt = context(&["Proceed? [y/n] y", "user@host:~$ "], 0);
+        assert_eq!(watchdog.classify(&prompt).0, PaneState::Idle);
+        let mut marked = context(&["Proceed? [y/n]"], 0);
+        marked.at_prompt = Some(true);
+        assert_eq!(watchdog.classify(&marked).0, PaneState::Idle);
+        marked.at_prompt = Some(false);
+        assert_eq!(watchdog.classify(&marked).0, PaneState::WaitingForApproval);
+    }
+}